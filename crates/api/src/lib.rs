@@ -0,0 +1,399 @@
+//! Request and response payloads of the hyleoof HTTP API, shared between the server and its
+//! clients. Also used to generate the OpenAPI document served on `/api/openapi.json`.
+
+use sdk::{api::TransactionStatus, ContractName, Identity, TxHash};
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+use utoipa::{IntoParams, ToSchema};
+
+/// Sends the configured faucet amount of `token` to `username`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct FaucetRequest {
+    /// Full identity, e.g. `bob.hydentity`.
+    pub username: String,
+    #[schema(value_type = String)]
+    pub token: ContractName,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TransferRequest {
+    /// Full identity of the sender, e.g. `bob.hydentity`.
+    pub username: String,
+    pub password: String,
+    /// Identity or contract name receiving the tokens.
+    pub recipient: String,
+    #[schema(value_type = String)]
+    pub token: ContractName,
+    pub amount: u128,
+}
+
+/// Allows `spender` to move `amount` of `token` on behalf of `username`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ApproveRequest {
+    pub username: String,
+    pub password: String,
+    pub spender: String,
+    pub token: String,
+    pub amount: u128,
+}
+
+/// Moves `amount` of `token` from `owner` to `recipient`, using the allowance `owner` gave
+/// to `username`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TransferFromRequest {
+    /// Full identity of the spender, e.g. `bob.hydentity`.
+    pub username: String,
+    pub password: String,
+    /// Account the tokens are taken from.
+    pub owner: String,
+    pub recipient: String,
+    #[schema(value_type = String)]
+    pub token: ContractName,
+    pub amount: u128,
+}
+
+/// Swaps `amount` of `token_a` against `token_b` through the AMM.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SwapRequest {
+    #[schema(value_type = String)]
+    pub username: Identity,
+    pub password: String,
+    #[schema(value_type = String)]
+    pub token_a: ContractName,
+    #[schema(value_type = String)]
+    pub token_b: ContractName,
+    pub amount: u128,
+}
+
+/// Registers a new hydentity account.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct RegisterRequest {
+    #[schema(value_type = String)]
+    pub username: Identity,
+    pub password: String,
+}
+
+/// Registration of a hydentity account, according to the local contract state.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct IdentityResponse {
+    #[schema(value_type = String)]
+    pub identity: Identity,
+    pub registered: bool,
+    /// Number of times the identity was verified on-chain, absent when not registered.
+    pub nonce: Option<u32>,
+    /// Whether the identity contract is able to change the credentials of its identities.
+    pub rotation_supported: bool,
+}
+
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct QuoteQuery {
+    #[param(value_type = String)]
+    pub token_a: ContractName,
+    #[param(value_type = String)]
+    pub token_b: ContractName,
+    /// Query strings don't support `u128` natively.
+    #[serde_as(as = "DisplayFromStr")]
+    #[param(value_type = String)]
+    pub amount: u128,
+}
+
+/// Amount of `token_b` received when swapping `amount_a` of `token_a`, from the server's
+/// view of the AMM reserves.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct QuoteResponse {
+    #[schema(value_type = String)]
+    pub token_a: ContractName,
+    #[schema(value_type = String)]
+    pub token_b: ContractName,
+    pub amount_a: u128,
+    pub amount_b: u128,
+}
+
+/// Blob transaction submitted by the server, its proof follows asynchronously.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TxHashResponse {
+    #[schema(value_type = String)]
+    pub tx_hash: TxHash,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TxStatusResponse {
+    #[schema(value_type = String)]
+    pub tx_hash: TxHash,
+    /// Status reported by the indexer, e.g. `Success`, `Failure` or `TimedOut`.
+    #[schema(value_type = String)]
+    pub status: TransactionStatus,
+}
+
+/// Outcome of a single readiness check.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Check {
+    pub ok: bool,
+    pub detail: String,
+}
+
+/// Detailed readiness report served on `/ready`, with a 503 status when `ready` is false.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct HealthReport {
+    pub ready: bool,
+    pub node: Check,
+    pub indexer: Check,
+    pub state_sync: Check,
+    pub prover: Check,
+    pub faucet: Check,
+}
+
+/// A request field that failed validation.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+/// Body of the 400 responses returned when a request fails validation.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct ValidationErrors {
+    pub errors: Vec<FieldError>,
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let errors: Vec<_> = self
+            .errors
+            .iter()
+            .map(|error| format!("{}: {}", error.field, error.message))
+            .collect();
+        write!(f, "invalid request: {}", errors.join(", "))
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+/// A single operation of a composite transaction, sent from the request identity.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Operation {
+    Transfer {
+        recipient: String,
+        #[schema(value_type = String)]
+        token: ContractName,
+        amount: u128,
+    },
+    Approve {
+        spender: String,
+        #[schema(value_type = String)]
+        token: ContractName,
+        amount: u128,
+    },
+    TransferFrom {
+        owner: String,
+        recipient: String,
+        #[schema(value_type = String)]
+        token: ContractName,
+        amount: u128,
+    },
+    Swap {
+        #[schema(value_type = String)]
+        token_a: ContractName,
+        #[schema(value_type = String)]
+        token_b: ContractName,
+        amount: u128,
+    },
+    /// Registers the identity, must be the only operation of its transaction.
+    Register,
+}
+
+/// Runs `operations` in a single transaction against a copy of the server states, without
+/// sending anything to the node.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SimulateRequest {
+    #[schema(value_type = String)]
+    pub username: Identity,
+    pub password: String,
+    pub operations: Vec<Operation>,
+}
+
+/// Ordered operations packed by `/api/batch` into one atomic transaction with a single
+/// identity proof.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BatchRequest {
+    #[schema(value_type = String)]
+    pub username: Identity,
+    pub password: String,
+    pub operations: Vec<Operation>,
+}
+
+/// Reserves, prices and activity of an AMM pair.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PoolResponse {
+    pub token_a: String,
+    pub token_b: String,
+    pub reserve_a: u128,
+    pub reserve_b: u128,
+    /// Amount of `token_b` one `token_a` is worth at the current reserves.
+    pub price_a_in_b: Option<f64>,
+    pub price_b_in_a: Option<f64>,
+    /// Constant product `reserve_a * reserve_b`, absent when it overflows.
+    pub k: Option<u128>,
+    pub reference_token: String,
+    /// Value of both reserves in `reference_token`, absent when a token has no pool with it.
+    pub tvl: Option<f64>,
+    /// Amounts swapped through this server over the last 24 hours, in and out of the pool.
+    pub volume_24h_a: u128,
+    pub volume_24h_b: u128,
+    pub swaps_24h: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CandleQuery {
+    /// Candle duration in seconds, one of the `prices.intervals_secs` of the server.
+    pub interval: u64,
+    /// Start, in seconds since the unix epoch. Defaults to 100 intervals before `to`.
+    pub from: Option<u64>,
+    /// End (excluded), in seconds since the unix epoch. Defaults to now.
+    pub to: Option<u64>,
+}
+
+/// Prices of `token_a` in `token_b` over one interval. Intervals without any observation are
+/// skipped.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Candle {
+    /// Start of the interval, in seconds since the unix epoch.
+    pub start: u64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    /// Amounts swapped through this server during the interval.
+    pub volume_a: u128,
+    pub volume_b: u128,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CandlesResponse {
+    pub token_a: String,
+    pub token_b: String,
+    pub interval: u64,
+    pub candles: Vec<Candle>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TwapQuery {
+    /// Averaging window in seconds, at most `twap.max_window_secs`.
+    pub window: u64,
+}
+
+/// Time-weighted average prices of a pair over observed blocks.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TwapResponse {
+    pub token_a: String,
+    pub token_b: String,
+    /// Seconds actually covered, shorter than asked while the history is short.
+    pub window: u64,
+    pub from_block: u64,
+    pub to_block: u64,
+    pub price_a_in_b: f64,
+    pub price_b_in_a: f64,
+}
+
+/// State changes a simulated transaction would make, per contract.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct SimulateResponse {
+    pub tokens: Vec<TokenDelta>,
+    pub pools: Vec<PoolDelta>,
+    /// Identities the transaction registers.
+    #[schema(value_type = Vec<String>)]
+    pub registered: Vec<Identity>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TokenDelta {
+    #[schema(value_type = String)]
+    pub token: ContractName,
+    pub balances: Vec<BalanceDelta>,
+    pub allowances: Vec<AllowanceDelta>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BalanceDelta {
+    pub account: String,
+    pub before: u128,
+    pub after: u128,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AllowanceDelta {
+    pub owner: String,
+    pub spender: String,
+    pub before: u128,
+    pub after: u128,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PoolDelta {
+    pub token_a: String,
+    pub token_b: String,
+    pub reserves_before: (u128, u128),
+    pub reserves_after: (u128, u128),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryOperation {
+    Transfer,
+    TransferFrom,
+    Approve,
+    Swap,
+    Register,
+    /// Backfilled from the indexer, not submitted by this server.
+    External,
+}
+
+/// An operation of a transaction involving an identity, as sender or counterparty.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct HistoryEntry {
+    #[schema(value_type = String)]
+    pub tx_hash: TxHash,
+    pub operation: HistoryOperation,
+    /// Identity of the transaction.
+    #[schema(value_type = String)]
+    pub identity: Identity,
+    /// Recipient, spender or owner of the operation.
+    pub counterparty: Option<String>,
+    #[schema(value_type = Vec<String>)]
+    pub tokens: Vec<ContractName>,
+    /// Amounts of `tokens`, for swaps the amount sent then the quoted amount received.
+    pub amounts: Vec<u128>,
+    /// Last status reported by the indexer, `Sequenced` until it settles.
+    #[schema(value_type = String)]
+    pub status: TransactionStatus,
+    /// Seconds since the unix epoch, unknown for backfilled entries.
+    pub submitted_at: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct HistoryQuery {
+    /// Only entries involving this token.
+    #[param(value_type = Option<String>)]
+    pub token: Option<ContractName>,
+    pub operation: Option<HistoryOperation>,
+    /// Entries to skip, newest first.
+    #[serde(default)]
+    pub offset: usize,
+    /// Defaults to 50, at most 200.
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct HistoryResponse {
+    pub entries: Vec<HistoryEntry>,
+    /// Entries matching the filters, across all pages.
+    pub total: usize,
+}
@@ -0,0 +1,314 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::{bail, Context, Result};
+use clap::{Args, Parser, Subcommand};
+use hyleoof_api::{
+    ApproveRequest, FaucetRequest, IdentityResponse, QuoteResponse, RegisterRequest, SwapRequest,
+    TransferFromRequest, TransferRequest, TxHashResponse, TxStatusResponse,
+};
+use reqwest::{Client, RequestBuilder};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Command line client for the hyleoof server.
+#[derive(Parser, Debug)]
+#[command(name = "hyleoof", version, about, long_about = None)]
+struct Cli {
+    #[arg(long, env = "HYLEOOF_URL", default_value = "http://127.0.0.1:3000")]
+    server_url: String,
+
+    /// Print raw JSON responses
+    #[arg(long, global = true, default_value_t = false)]
+    json: bool,
+
+    /// Sent as `Idempotency-Key`, retrying with the same key never submits twice
+    #[arg(long, global = true)]
+    idempotency_key: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Args, Debug)]
+struct Credentials {
+    /// Full identity, e.g. `bob.hydentity`
+    #[arg(long, env = "HYLEOOF_USERNAME")]
+    username: String,
+
+    #[arg(long, env = "HYLEOOF_PASSWORD", hide_env_values = true)]
+    password: Option<String>,
+
+    /// File containing the password, trailing newlines are ignored
+    #[arg(long, env = "HYLEOOF_PASSWORD_FILE", conflicts_with = "password")]
+    password_file: Option<PathBuf>,
+}
+
+impl Credentials {
+    fn password(&self) -> Result<String> {
+        match (&self.password, &self.password_file) {
+            (Some(password), _) => Ok(password.clone()),
+            (None, Some(path)) => Ok(fs::read_to_string(path)
+                .with_context(|| format!("reading password file {}", path.display()))?
+                .trim_end_matches(['\n', '\r'])
+                .to_string()),
+            (None, None) => bail!("a password is required, use --password or --password-file"),
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Register a new hydentity account
+    Register(Credentials),
+    /// Show whether an identity is registered
+    Identity { name: String },
+    /// Receive tokens from the faucet
+    Faucet {
+        #[arg(long, env = "HYLEOOF_USERNAME")]
+        username: String,
+        #[arg(long)]
+        token: String,
+    },
+    Transfer {
+        #[command(flatten)]
+        credentials: Credentials,
+        #[arg(long)]
+        recipient: String,
+        #[arg(long)]
+        token: String,
+        #[arg(long)]
+        amount: u128,
+    },
+    /// Move tokens of `owner` using the allowance they gave you
+    TransferFrom {
+        #[command(flatten)]
+        credentials: Credentials,
+        #[arg(long)]
+        owner: String,
+        #[arg(long)]
+        recipient: String,
+        #[arg(long)]
+        token: String,
+        #[arg(long)]
+        amount: u128,
+    },
+    /// Allow `spender` to move tokens on your behalf
+    Approve {
+        #[command(flatten)]
+        credentials: Credentials,
+        #[arg(long, default_value = "amm")]
+        spender: String,
+        #[arg(long)]
+        token: String,
+        #[arg(long)]
+        amount: u128,
+    },
+    /// Swap `amount` of `from` against `to` through the AMM
+    Swap {
+        #[command(flatten)]
+        credentials: Credentials,
+        #[arg(long)]
+        from: String,
+        #[arg(long)]
+        to: String,
+        #[arg(long)]
+        amount: u128,
+    },
+    /// Show how much `to` a swap of `amount` `from` would return
+    Quote {
+        #[arg(long)]
+        from: String,
+        #[arg(long)]
+        to: String,
+        #[arg(long)]
+        amount: u128,
+    },
+    /// Show the on-chain status of a transaction
+    TxStatus { tx_hash: String },
+}
+
+struct ServerClient {
+    url: String,
+    client: Client,
+    idempotency_key: Option<String>,
+}
+
+impl ServerClient {
+    async fn post<B: Serialize, T: DeserializeOwned>(&self, endpoint: &str, body: &B) -> Result<T> {
+        let mut request = self.client.post(self.endpoint(endpoint)).json(body);
+        if let Some(key) = &self.idempotency_key {
+            request = request.header("Idempotency-Key", key);
+        }
+        let value = self.send(request).await?;
+        Ok(serde_json::from_value(value)?)
+    }
+
+    async fn get<T: DeserializeOwned>(
+        &self,
+        endpoint: &str,
+        query: &[(&str, String)],
+    ) -> Result<T> {
+        let value = self
+            .send(self.client.get(self.endpoint(endpoint)).query(query))
+            .await?;
+        Ok(serde_json::from_value(value)?)
+    }
+
+    fn endpoint(&self, endpoint: &str) -> String {
+        format!("{}{endpoint}", self.url.trim_end_matches('/'))
+    }
+
+    async fn send(&self, request: RequestBuilder) -> Result<serde_json::Value> {
+        let response = request.send().await.context("sending request to server")?;
+        let status = response.status();
+        if !status.is_success() {
+            bail!("server returned {status}: {}", response.text().await?);
+        }
+        Ok(response.json().await?)
+    }
+}
+
+fn print_tx(json: bool, response: TxHashResponse) -> Result<()> {
+    if json {
+        print_json(&response)
+    } else {
+        println!("Transaction {} submitted", response.tx_hash);
+        Ok(())
+    }
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let server = ServerClient {
+        url: cli.server_url,
+        client: Client::new(),
+        idempotency_key: cli.idempotency_key,
+    };
+
+    match cli.command {
+        Command::Register(credentials) => {
+            let body = RegisterRequest {
+                username: credentials.username.clone().into(),
+                password: credentials.password()?,
+            };
+            print_tx(cli.json, server.post("/api/register", &body).await?)
+        }
+        Command::Identity { name } => {
+            let identity: IdentityResponse =
+                server.get(&format!("/api/identity/{name}"), &[]).await?;
+            if cli.json {
+                print_json(&identity)
+            } else if identity.registered {
+                println!("{} is registered", identity.identity);
+                Ok(())
+            } else {
+                println!("{} is not registered", identity.identity);
+                Ok(())
+            }
+        }
+        Command::Faucet { username, token } => {
+            let body = FaucetRequest {
+                username,
+                token: token.into(),
+            };
+            print_tx(cli.json, server.post("/api/faucet", &body).await?)
+        }
+        Command::Transfer {
+            credentials,
+            recipient,
+            token,
+            amount,
+        } => {
+            let body = TransferRequest {
+                password: credentials.password()?,
+                username: credentials.username,
+                recipient,
+                token: token.into(),
+                amount,
+            };
+            print_tx(cli.json, server.post("/api/transfer", &body).await?)
+        }
+        Command::TransferFrom {
+            credentials,
+            owner,
+            recipient,
+            token,
+            amount,
+        } => {
+            let body = TransferFromRequest {
+                password: credentials.password()?,
+                username: credentials.username,
+                owner,
+                recipient,
+                token: token.into(),
+                amount,
+            };
+            print_tx(cli.json, server.post("/api/transfer-from", &body).await?)
+        }
+        Command::Approve {
+            credentials,
+            spender,
+            token,
+            amount,
+        } => {
+            let body = ApproveRequest {
+                password: credentials.password()?,
+                username: credentials.username,
+                spender,
+                token,
+                amount,
+            };
+            print_tx(cli.json, server.post("/api/approve", &body).await?)
+        }
+        Command::Swap {
+            credentials,
+            from,
+            to,
+            amount,
+        } => {
+            let body = SwapRequest {
+                password: credentials.password()?,
+                username: credentials.username.into(),
+                token_a: from.into(),
+                token_b: to.into(),
+                amount,
+            };
+            print_tx(cli.json, server.post("/api/swap", &body).await?)
+        }
+        Command::Quote { from, to, amount } => {
+            let quote: QuoteResponse = server
+                .get(
+                    "/api/quote",
+                    &[
+                        ("token_a", from),
+                        ("token_b", to),
+                        ("amount", amount.to_string()),
+                    ],
+                )
+                .await?;
+            if cli.json {
+                print_json(&quote)
+            } else {
+                println!(
+                    "{} {} -> {} {}",
+                    quote.amount_a, quote.token_a, quote.amount_b, quote.token_b
+                );
+                Ok(())
+            }
+        }
+        Command::TxStatus { tx_hash } => {
+            let status: TxStatusResponse = server.get(&format!("/api/tx/{tx_hash}"), &[]).await?;
+            if cli.json {
+                print_json(&status)
+            } else {
+                println!("{}: {:?}", status.tx_hash, status.status);
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,274 @@
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::{bail, Context, Result};
+use axum::http::HeaderValue;
+use config::{Config, Environment, File, FileFormat};
+use reqwest::Url;
+use sdk::{ContractName, Identity};
+use serde::Deserialize;
+
+use crate::identity;
+
+/// Server configuration, layered from the bundled defaults, an optional config file,
+/// `HYLEOOF_*` environment variables (nested keys use `__`, e.g. `HYLEOOF_FAUCET__PASSWORD`)
+/// and command line flags, in increasing priority.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Conf {
+    pub node_url: String,
+    pub indexer_url: String,
+    /// Address the HTTP server binds to.
+    pub host: String,
+    /// Directory where the server keeps its local state across restarts.
+    pub data_dir: PathBuf,
+    /// Genesis manifest, the bundled one is used when unset.
+    pub genesis: Option<PathBuf>,
+    pub faucet: Faucet,
+    pub identities: Identities,
+    pub prover: Prover,
+    pub cors: Cors,
+    pub timeouts: Timeouts,
+    pub log: Log,
+    pub state_sync: StateSync,
+    pub health: Health,
+    pub idempotency: Idempotency,
+    pub history: History,
+    pub pools: Pools,
+    pub prices: Prices,
+    pub twap: Twap,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Faucet {
+    pub identity: Identity,
+    pub password: String,
+    /// Amount sent by each `/api/faucet` call.
+    pub amount: u128,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Identities {
+    /// Identity contracts whose provider is registered, the first one is the default for names
+    /// given without a suffix.
+    pub providers: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Prover {
+    pub backend: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Cors {
+    pub allowed_origins: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Timeouts {
+    pub request_secs: u64,
+    pub node_client_secs: u64,
+    pub bootstrap_secs: u64,
+    /// Time given to the prover to drain its queue on shutdown.
+    pub shutdown_secs: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Log {
+    pub format: LogFormat,
+    /// OTLP/gRPC collector spans are exported to, needs the `otlp` feature.
+    pub otlp_endpoint: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StateSync {
+    /// How often the local states are replaced with the ones of the indexer.
+    pub interval_secs: u64,
+}
+
+/// Thresholds past which `/ready` reports the server as not ready.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Health {
+    /// Blocks the indexer may lag behind the node.
+    pub max_indexer_lag: u64,
+    /// Time the oldest transaction may wait in the proving queue.
+    pub max_queue_age_secs: u64,
+    /// Time since the local states last matched the ones of the indexer.
+    pub max_sync_age_secs: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Idempotency {
+    /// How long an `Idempotency-Key` is remembered.
+    pub ttl_secs: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct History {
+    /// Also list the token transactions of an identity known to the indexer.
+    pub backfill: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Pools {
+    /// Manifest token the value locked in the pools is expressed in.
+    pub reference_token: ContractName,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Prices {
+    /// Candle durations served by `/api/pools/{a}/{b}/candles`.
+    pub intervals_secs: Vec<u64>,
+    /// How often the AMM reserves are read from the indexer, 0 disables it.
+    pub sync_interval_secs: u64,
+    /// How long price observations are kept.
+    pub retention_secs: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Twap {
+    /// Longest window served by `/api/pools/{a}/{b}/twap`.
+    pub max_window_secs: u64,
+    /// Seconds between two walks of the new blocks, 0 disables the oracle.
+    pub sync_interval_secs: u64,
+    /// Blocks observed per walk at most, the rest are caught up by the next ones.
+    pub max_blocks_per_sync: u64,
+}
+
+/// Values given on the command line, overriding every other source.
+#[derive(Debug, Default)]
+pub struct Overrides {
+    pub node_url: Option<String>,
+    pub indexer_url: Option<String>,
+    pub host: Option<String>,
+    pub data_dir: Option<String>,
+    pub genesis: Option<String>,
+}
+
+const DEFAULTS: &str = include_str!("conf_defaults.toml");
+
+impl Conf {
+    pub fn new(config_file: Option<&str>, overrides: Overrides) -> Result<Self> {
+        let mut builder = Config::builder()
+            .add_source(File::from_str(DEFAULTS, FileFormat::Toml))
+            .add_source(
+                File::with_name(config_file.unwrap_or("hyleoof")).required(config_file.is_some()),
+            )
+            .add_source(
+                Environment::with_prefix("HYLEOOF")
+                    .prefix_separator("_")
+                    .separator("__")
+                    .list_separator(",")
+                    .with_list_parse_key("cors.allowed_origins")
+                    .with_list_parse_key("identities.providers")
+                    .try_parsing(true),
+            );
+
+        // Historical variable names, kept working for existing deployments.
+        for (var, key) in [("NODE_URL", "node_url"), ("INDEXER_URL", "indexer_url")] {
+            builder = builder.set_override_option(key, std::env::var(var).ok())?;
+        }
+
+        let conf: Conf = builder
+            .set_override_option("node_url", overrides.node_url)?
+            .set_override_option("indexer_url", overrides.indexer_url)?
+            .set_override_option("host", overrides.host)?
+            .set_override_option("data_dir", overrides.data_dir)?
+            .set_override_option("genesis", overrides.genesis)?
+            .build()?
+            .try_deserialize()
+            .context("invalid configuration")?;
+        conf.validate()?;
+        Ok(conf)
+    }
+
+    /// The bundled defaults alone, whatever the environment.
+    #[cfg(test)]
+    pub fn defaults() -> Self {
+        Config::builder()
+            .add_source(File::from_str(DEFAULTS, FileFormat::Toml))
+            .build()
+            .and_then(Config::try_deserialize)
+            .expect("the bundled defaults are valid")
+    }
+
+    fn validate(&self) -> Result<()> {
+        self.node_url()?;
+        self.indexer_url()?;
+        if self.host.is_empty() {
+            bail!("host must not be empty");
+        }
+        if self.faucet.identity.0.is_empty() {
+            bail!("faucet.identity must not be empty");
+        }
+        if self.faucet.amount == 0 {
+            bail!("faucet.amount must be greater than 0");
+        }
+        let providers = &self.identities.providers;
+        if providers.is_empty() {
+            bail!("identities.providers must not be empty");
+        }
+        for (i, contract) in providers.iter().enumerate() {
+            if identity::provider(contract).is_none() {
+                bail!("identities.providers: no identity provider for contract {contract:?}");
+            }
+            if providers[..i].contains(contract) {
+                bail!("identities.providers: {contract:?} is listed twice");
+            }
+        }
+        if !self
+            .faucet
+            .identity
+            .0
+            .split_once('.')
+            .is_some_and(|(_, contract)| providers.iter().any(|p| p == contract))
+        {
+            bail!(
+                "faucet.identity {} is not handled by identities.providers",
+                self.faucet.identity.0
+            );
+        }
+        if self.prices.intervals_secs.contains(&0) {
+            bail!("prices.intervals_secs must be greater than 0");
+        }
+        if self.twap.max_blocks_per_sync == 0 {
+            bail!("twap.max_blocks_per_sync must be greater than 0");
+        }
+        for origin in &self.cors.allowed_origins {
+            if origin != "*" {
+                HeaderValue::from_str(origin)
+                    .with_context(|| format!("invalid CORS origin {origin:?}"))?;
+            }
+        }
+        let timeouts = [
+            ("timeouts.request_secs", self.timeouts.request_secs),
+            ("timeouts.node_client_secs", self.timeouts.node_client_secs),
+            ("timeouts.bootstrap_secs", self.timeouts.bootstrap_secs),
+            ("state_sync.interval_secs", self.state_sync.interval_secs),
+        ];
+        for (key, secs) in timeouts {
+            if secs == 0 {
+                bail!("{key} must be greater than 0");
+            }
+        }
+        Ok(())
+    }
+
+    pub fn node_url(&self) -> Result<Url> {
+        Url::parse(&self.node_url).with_context(|| format!("invalid node_url {:?}", self.node_url))
+    }
+
+    pub fn indexer_url(&self) -> Result<Url> {
+        Url::parse(&self.indexer_url)
+            .with_context(|| format!("invalid indexer_url {:?}", self.indexer_url))
+    }
+
+    pub fn bootstrap_timeout(&self) -> Duration {
+        Duration::from_secs(self.timeouts.bootstrap_secs)
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result};
+use borsh::BorshDeserialize;
+use hyllar::HyllarToken;
+use serde::Serialize;
+
+use crate::{pools, States};
+
+/// Mirrors the borsh layout of `HyllarToken`, whose allowances are keyed by
+/// `(owner, spender)` tuples that JSON objects cannot hold.
+#[derive(BorshDeserialize)]
+#[cfg_attr(test, derive(borsh::BorshSerialize))]
+struct TokenEntries {
+    total_supply: u128,
+    balances: BTreeMap<String, u128>,
+    allowances: BTreeMap<(String, String), u128>,
+}
+
+#[derive(Serialize)]
+struct TokenDump {
+    total_supply: u128,
+    balances: Vec<Balance>,
+    allowances: Vec<Allowance>,
+}
+
+#[derive(Serialize)]
+struct Balance {
+    account: String,
+    amount: u128,
+}
+
+#[derive(Serialize)]
+struct Allowance {
+    owner: String,
+    spender: String,
+    amount: u128,
+}
+
+fn token(state: &HyllarToken) -> Result<TokenDump> {
+    let TokenEntries {
+        total_supply,
+        balances,
+        allowances,
+    } = borsh::from_slice(&borsh::to_vec(state)?).context("decoding token state")?;
+    Ok(TokenDump {
+        total_supply,
+        balances: balances
+            .into_iter()
+            .map(|(account, amount)| Balance { account, amount })
+            .collect(),
+        allowances: allowances
+            .into_iter()
+            .map(|((owner, spender), amount)| Allowance {
+                owner,
+                spender,
+                amount,
+            })
+            .collect(),
+    })
+}
+
+/// JSON view of the contract states, with the maps keyed by tuples turned into lists.
+pub fn dump(states: &States) -> Result<serde_json::Value> {
+    let pools = pools::reserves(&states.amm)?;
+    Ok(serde_json::json!({
+        "hyllar": serde_json::to_value(token(&states.hyllar)?)?,
+        "hyllar2": serde_json::to_value(token(&states.hyllar2)?)?,
+        "hydentity": serde_json::to_value(&states.hydentity)?,
+        "amm": { "pools": serde_json::to_value(pools)? },
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use hyllar::HyllarTokenContract;
+    use sdk::erc20::ERC20;
+
+    use super::*;
+
+    #[test]
+    fn token_entries_match_the_contract_state() {
+        let mut contract = HyllarTokenContract::init(
+            HyllarToken::new(1000, "faucet".to_string()),
+            "faucet".into(),
+        );
+        contract.transfer("bob", 300).unwrap();
+        contract.approve("amm", 50).unwrap();
+        let state = contract.state();
+        let entries: TokenEntries = borsh::from_slice(&borsh::to_vec(&state).unwrap()).unwrap();
+        assert_eq!(
+            borsh::to_vec(&entries).unwrap(),
+            borsh::to_vec(&state).unwrap()
+        );
+
+        let dump = token(&state).unwrap();
+        assert_eq!(dump.total_supply, 1000);
+        let balances: Vec<_> = dump
+            .balances
+            .iter()
+            .map(|b| (b.account.as_str(), b.amount))
+            .collect();
+        assert_eq!(balances, [("bob", 300), ("faucet", 700)]);
+        let allowances: Vec<_> = dump
+            .allowances
+            .iter()
+            .map(|a| (a.owner.as_str(), a.spender.as_str(), a.amount))
+            .collect();
+        assert_eq!(allowances, [("faucet", "amm", 50)]);
+    }
+}
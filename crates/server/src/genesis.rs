@@ -0,0 +1,370 @@
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::Path,
+};
+
+use amm::{AmmState, UnorderedTokenPair};
+use anyhow::{anyhow, bail, Context, Result};
+use hydentity::Hydentity;
+use hyllar::{HyllarToken, HyllarTokenContract};
+use sdk::{erc20::ERC20, ContractName, Digestable, Identity, StateDigest};
+use serde::Deserialize;
+
+const DEFAULT_GENESIS: &str = include_str!("../genesis.toml");
+
+/// Declarative description of the contracts hyleoof bootstraps on the node.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Genesis {
+    pub contracts: Vec<ContractManifest>,
+    #[serde(default)]
+    pub pools: Vec<Pool>,
+    #[serde(default)]
+    pub transfers: Vec<SeedTransfer>,
+    #[serde(default)]
+    pub approvals: Vec<SeedApproval>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContractKind {
+    Token,
+    Amm,
+    Identity,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ContractManifest {
+    pub name: ContractName,
+    pub kind: ContractKind,
+    /// Name of a bundled contract (`hyllar`, `amm`, `hydentity`) or path to an ELF file.
+    pub elf: String,
+    #[serde(default = "default_verifier")]
+    pub verifier: String,
+    /// When false the contract is expected to already exist on-chain.
+    #[serde(default = "default_true")]
+    pub register: bool,
+    /// Initial supply, owned by the faucet. Only for tokens.
+    pub supply: Option<u128>,
+    #[serde(default)]
+    pub depends_on: Vec<ContractName>,
+    #[serde(default)]
+    pub on_mismatch: MismatchPolicy,
+}
+
+/// What to do when the on-chain program id differs from the bundled ELF image id.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MismatchPolicy {
+    /// Stop the server with a detailed report.
+    #[default]
+    Refuse,
+    /// Keep serving against the old program, rejecting transactions that touch it.
+    ReadOnly,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Pool {
+    pub amm: ContractName,
+    pub tokens: (ContractName, ContractName),
+    pub reserves: (u128, u128),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SeedTransfer {
+    pub token: ContractName,
+    pub recipient: String,
+    pub amount: u128,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SeedApproval {
+    pub token: ContractName,
+    pub spender: String,
+    pub amount: u128,
+}
+
+fn default_verifier() -> String {
+    "risc0".to_string()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Genesis {
+    /// Loads the manifest at `path` (TOML, or JSON when the extension is `.json`),
+    /// falling back to the one bundled with the server.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let genesis: Genesis = match path {
+            Some(path) => {
+                let content = fs::read_to_string(path)
+                    .with_context(|| format!("reading genesis manifest {}", path.display()))?;
+                if path.extension().is_some_and(|ext| ext == "json") {
+                    serde_json::from_str(&content)?
+                } else {
+                    toml::from_str(&content)?
+                }
+            }
+            None => toml::from_str(DEFAULT_GENESIS)?,
+        };
+        genesis.validate()?;
+        Ok(genesis)
+    }
+
+    fn validate(&self) -> Result<()> {
+        let mut names = BTreeSet::new();
+        for contract in &self.contracts {
+            if !names.insert(contract.name.0.as_str()) {
+                bail!("Contract {} is declared twice", contract.name);
+            }
+            if contract.register
+                && contract.kind == ContractKind::Token
+                && contract.supply.is_none()
+            {
+                bail!(
+                    "Token {} must declare a supply to be registered",
+                    contract.name
+                );
+            }
+        }
+        for pool in &self.pools {
+            self.expect_kind(&pool.amm, ContractKind::Amm)?;
+            self.expect_kind(&pool.tokens.0, ContractKind::Token)?;
+            self.expect_kind(&pool.tokens.1, ContractKind::Token)?;
+        }
+        for transfer in &self.transfers {
+            self.expect_kind(&transfer.token, ContractKind::Token)?;
+        }
+        for approval in &self.approvals {
+            self.expect_kind(&approval.token, ContractKind::Token)?;
+        }
+        self.registration_order()?;
+        Ok(())
+    }
+
+    fn expect_kind(&self, name: &ContractName, kind: ContractKind) -> Result<()> {
+        match self.contract(name) {
+            Some(contract) if contract.kind == kind => Ok(()),
+            Some(contract) => bail!(
+                "Contract {name} is a {:?}, expected {kind:?}",
+                contract.kind
+            ),
+            None => bail!("Contract {name} is not declared in the genesis manifest"),
+        }
+    }
+
+    pub fn contract(&self, name: &ContractName) -> Option<&ContractManifest> {
+        self.contracts.iter().find(|c| &c.name == name)
+    }
+
+    fn dependencies(&self, contract: &ContractManifest) -> Vec<&ContractName> {
+        let pools = self
+            .pools
+            .iter()
+            .filter(|pool| pool.amm == contract.name)
+            .flat_map(|pool| [&pool.tokens.0, &pool.tokens.1]);
+        contract.depends_on.iter().chain(pools).collect()
+    }
+
+    /// Contracts sorted so that each one comes after its dependencies, keeping the manifest
+    /// order otherwise.
+    pub fn registration_order(&self) -> Result<Vec<&ContractManifest>> {
+        let mut ordered: Vec<&ContractManifest> = Vec::with_capacity(self.contracts.len());
+        let mut done = BTreeSet::new();
+
+        while ordered.len() < self.contracts.len() {
+            let next = self.contracts.iter().find(|contract| {
+                !done.contains(contract.name.0.as_str())
+                    && self
+                        .dependencies(contract)
+                        .iter()
+                        .all(|dep| done.contains(dep.0.as_str()))
+            });
+            match next {
+                Some(contract) => {
+                    done.insert(contract.name.0.as_str());
+                    ordered.push(contract);
+                }
+                None => {
+                    let pending: Vec<_> = self
+                        .contracts
+                        .iter()
+                        .filter(|c| !done.contains(c.name.0.as_str()))
+                        .map(|c| c.name.0.as_str())
+                        .collect();
+                    bail!(
+                        "Unresolvable contract dependencies in genesis manifest: {}",
+                        pending.join(", ")
+                    );
+                }
+            }
+        }
+        Ok(ordered)
+    }
+
+    pub fn transfers_for<'a>(
+        &'a self,
+        token: &'a ContractName,
+    ) -> impl Iterator<Item = &'a SeedTransfer> {
+        self.transfers.iter().filter(move |t| &t.token == token)
+    }
+
+    pub fn approvals_for<'a>(
+        &'a self,
+        token: &'a ContractName,
+    ) -> impl Iterator<Item = &'a SeedApproval> {
+        self.approvals.iter().filter(move |a| &a.token == token)
+    }
+
+    /// State digest the contract is registered with. Tokens start with the faucet owning the
+    /// whole supply, with the seed transfers and approvals already applied.
+    pub fn initial_state(
+        &self,
+        contract: &ContractManifest,
+        faucet: &Identity,
+    ) -> Result<StateDigest> {
+        match contract.kind {
+            ContractKind::Token => {
+                let supply = contract
+                    .supply
+                    .ok_or_else(|| anyhow!("Token {} has no supply", contract.name))?;
+                let mut token = HyllarTokenContract::init(
+                    HyllarToken::new(supply, faucet.0.clone()),
+                    faucet.clone(),
+                );
+                for transfer in self.transfers_for(&contract.name) {
+                    token
+                        .transfer(&transfer.recipient, transfer.amount)
+                        .map_err(|e| anyhow!(e))?;
+                }
+                for approval in self.approvals_for(&contract.name) {
+                    token
+                        .approve(&approval.spender, approval.amount)
+                        .map_err(|e| anyhow!(e))?;
+                }
+                Ok(token.state().as_digest())
+            }
+            ContractKind::Amm => {
+                let pairs: BTreeMap<_, _> = self
+                    .pools
+                    .iter()
+                    .filter(|pool| pool.amm == contract.name)
+                    .map(|pool| {
+                        (
+                            UnorderedTokenPair::new(
+                                pool.tokens.0 .0.clone(),
+                                pool.tokens.1 .0.clone(),
+                            ),
+                            pool.reserves,
+                        )
+                    })
+                    .collect();
+                Ok(AmmState::new(pairs).as_digest())
+            }
+            ContractKind::Identity => Ok(Hydentity::new().as_digest()),
+        }
+    }
+}
+
+impl ContractManifest {
+    pub fn elf(&self) -> Result<Cow<'static, [u8]>> {
+        Ok(match self.elf.as_str() {
+            "hyllar" => Cow::Borrowed(hyllar::client::metadata::HYLLAR_ELF),
+            "amm" => Cow::Borrowed(amm::client::metadata::AMM_ELF),
+            "hydentity" => Cow::Borrowed(hydentity::client::metadata::HYDENTITY_ELF),
+            path => Cow::Owned(
+                fs::read(path)
+                    .with_context(|| format!("reading ELF for contract {}", self.name))?,
+            ),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(genesis: &Genesis) -> Vec<&str> {
+        genesis
+            .registration_order()
+            .unwrap()
+            .iter()
+            .map(|contract| contract.name.0.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn registration_order_keeps_the_manifest_order_without_dependencies() {
+        let genesis: Genesis = toml::from_str(DEFAULT_GENESIS).unwrap();
+        assert_eq!(names(&genesis), ["hydentity", "hyllar", "hyllar2", "amm"]);
+    }
+
+    #[test]
+    fn registration_order_puts_pool_tokens_and_dependencies_first() {
+        let genesis: Genesis = toml::from_str(
+            r#"
+            [[contracts]]
+            name = "amm"
+            kind = "amm"
+            elf = "amm"
+
+            [[contracts]]
+            name = "late"
+            kind = "token"
+            elf = "hyllar"
+            supply = 1
+            depends_on = ["b"]
+
+            [[contracts]]
+            name = "a"
+            kind = "token"
+            elf = "hyllar"
+            supply = 1
+
+            [[contracts]]
+            name = "b"
+            kind = "token"
+            elf = "hyllar"
+            supply = 1
+
+            [[pools]]
+            amm = "amm"
+            tokens = ["a", "b"]
+            reserves = [1, 1]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(names(&genesis), ["a", "b", "amm", "late"]);
+    }
+
+    #[test]
+    fn registration_order_rejects_cycles() {
+        let genesis: Genesis = toml::from_str(
+            r#"
+            [[contracts]]
+            name = "a"
+            kind = "token"
+            elf = "hyllar"
+            supply = 1
+            depends_on = ["b"]
+
+            [[contracts]]
+            name = "b"
+            kind = "token"
+            elf = "hyllar"
+            supply = 1
+            depends_on = ["a"]
+            "#,
+        )
+        .unwrap();
+        let error = genesis.registration_order().unwrap_err().to_string();
+        assert!(error.contains("a, b"), "{error}");
+    }
+}
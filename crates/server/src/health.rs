@@ -0,0 +1,200 @@
+use std::time::SystemTime;
+
+use hyleoof_api::{Check, HealthReport};
+use sdk::ContractName;
+
+use crate::RouterCtx;
+
+fn passed(detail: impl Into<String>) -> Check {
+    Check {
+        ok: true,
+        detail: detail.into(),
+    }
+}
+
+fn failed(detail: impl Into<String>) -> Check {
+    Check {
+        ok: false,
+        detail: detail.into(),
+    }
+}
+
+/// Checks the node and indexer connectivity, the local states, the prover worker and the
+/// faucet balances.
+pub async fn report(ctx: &RouterCtx) -> HealthReport {
+    let conf = &ctx.conf.health;
+
+    // Only copy what is needed out of the app, the network checks run without the lock.
+    let (node, prover, synced_at, unsettled, balances) = {
+        let app = ctx.lock_app().await;
+        (
+            app.client.clone(),
+            app.prover.clone(),
+            app.synced_at,
+            app.unsettled.lock().unwrap().len(),
+            app.faucet_balances(),
+        )
+    };
+
+    let node_height = node.get_block_height().await;
+    let node_check = match &node_height {
+        Ok(height) => passed(format!("block height {}", height.0)),
+        Err(e) => failed(format!("unreachable: {e:#}")),
+    };
+
+    let indexer_check = match ctx.indexer.get_last_block().await {
+        Err(e) => failed(format!("unreachable: {e:#}")),
+        Ok(block) => indexer_lag(
+            block.height,
+            node_height.as_ref().ok().map(|height| height.0),
+            conf.max_indexer_lag,
+        ),
+    };
+
+    let age = SystemTime::now()
+        .duration_since(synced_at)
+        .unwrap_or_default()
+        .as_secs();
+    let state_sync = state_age(age, unsettled, conf.max_sync_age_secs);
+
+    let prover_check = if !prover.is_alive() {
+        failed("prover worker stopped")
+    } else {
+        match prover.oldest_queued() {
+            Some(age) if age.as_secs() > conf.max_queue_age_secs => failed(format!(
+                "oldest queued transaction waiting for {}s",
+                age.as_secs()
+            )),
+            Some(age) => passed(format!(
+                "oldest queued transaction waiting for {}s",
+                age.as_secs()
+            )),
+            None => passed("queue empty"),
+        }
+    };
+
+    let faucet = faucet_balances(balances, ctx.conf.faucet.amount);
+
+    HealthReport {
+        ready: [
+            &node_check,
+            &indexer_check,
+            &state_sync,
+            &prover_check,
+            &faucet,
+        ]
+        .iter()
+        .all(|check| check.ok),
+        node: node_check,
+        indexer: indexer_check,
+        state_sync,
+        prover: prover_check,
+        faucet,
+    }
+}
+
+/// Fails when the indexer lags more than `max_lag` blocks behind the node, passes when the
+/// node height is unknown.
+fn indexer_lag(indexer_height: u64, node_height: Option<u64>, max_lag: u64) -> Check {
+    let Some(node_height) = node_height else {
+        return passed(format!("block height {indexer_height}"));
+    };
+    let lag = node_height.saturating_sub(indexer_height);
+    let detail = format!("block height {indexer_height}, {lag} blocks behind the node");
+    if lag > max_lag {
+        failed(detail)
+    } else {
+        passed(detail)
+    }
+}
+
+/// Fails when the local states were last resynced more than `max_age` seconds ago.
+fn state_age(age: u64, unsettled: usize, max_age: u64) -> Check {
+    let detail = format!(
+        "states last resynced from the indexer {age}s ago, {unsettled} transactions not settled"
+    );
+    if age > max_age {
+        failed(detail)
+    } else {
+        passed(detail)
+    }
+}
+
+/// Fails when the faucet cannot send `amount` of every token.
+fn faucet_balances(balances: Vec<(ContractName, Option<u128>)>, amount: u128) -> Check {
+    let mut low = vec![];
+    let mut details = vec![];
+    for (token, balance) in balances {
+        match balance {
+            Some(balance) => {
+                if balance < amount {
+                    low.push(token.0.clone());
+                }
+                details.push(format!("{token}: {balance}"));
+            }
+            None => {
+                low.push(token.0.clone());
+                details.push(format!("{token}: no balance"));
+            }
+        }
+    }
+    if low.is_empty() {
+        passed(details.join(", "))
+    } else {
+        failed(format!(
+            "{} (below {amount} for {})",
+            details.join(", "),
+            low.join(", ")
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indexer_lag_is_bounded() {
+        assert!(indexer_lag(95, Some(100), 5).ok);
+        let check = indexer_lag(94, Some(100), 5);
+        assert!(!check.ok);
+        assert_eq!(check.detail, "block height 94, 6 blocks behind the node");
+        // An indexer ahead of the node does not lag, and an unreachable node is reported
+        // by its own check.
+        assert!(indexer_lag(101, Some(100), 0).ok);
+        assert!(indexer_lag(0, None, 0).ok);
+    }
+
+    #[test]
+    fn stale_states_fail() {
+        assert!(state_age(60, 2, 60).ok);
+        let check = state_age(61, 2, 60);
+        assert!(!check.ok);
+        assert_eq!(
+            check.detail,
+            "states last resynced from the indexer 61s ago, 2 transactions not settled"
+        );
+    }
+
+    #[test]
+    fn faucet_needs_enough_of_every_token() {
+        let balance = |token: &str, balance| (ContractName::from(token), balance);
+        assert!(
+            faucet_balances(
+                vec![balance("hyllar", Some(10)), balance("hyllar2", Some(10))],
+                10
+            )
+            .ok
+        );
+
+        let check = faucet_balances(
+            vec![balance("hyllar", Some(9)), balance("hyllar2", None)],
+            10,
+        );
+        assert!(!check.ok);
+        assert_eq!(
+            check.detail,
+            "hyllar: 9, hyllar2: no balance (below 10 for hyllar, hyllar2)"
+        );
+    }
+}
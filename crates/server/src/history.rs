@@ -0,0 +1,549 @@
+use std::{
+    collections::BTreeSet,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::Path,
+    sync::Mutex as SyncMutex,
+    time::SystemTime,
+};
+
+use anyhow::{Context, Result};
+use client_sdk::rest_client::IndexerApiHttpClient;
+use hyleoof_api::{HistoryEntry, HistoryOperation, HistoryQuery, HistoryResponse, Operation};
+use sdk::{
+    api::TransactionStatus, erc20::ERC20Action, BlobData, ContractName, Identity,
+    StructuredBlobData, TxHash,
+};
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinSet;
+use tracing::{error, warn};
+
+use crate::{RouterCtx, TOKENS};
+
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 200;
+/// Most transactions whose status is fetched from the indexer for a single page.
+const MAX_STATUS_REFRESH: usize = 20;
+
+/// Amounts of each token of a pair swapped in or out of the pool.
+#[derive(Debug, Default)]
+pub struct SwapVolume {
+    pub amount_a: u128,
+    pub amount_b: u128,
+    pub swaps: usize,
+}
+
+/// Line of the history log. Each change is appended to the log, which is replayed on startup.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "record", rename_all = "snake_case")]
+enum Record {
+    /// Operation of a transaction submitted by the server.
+    Submitted(HistoryEntry),
+    /// Transaction found on the indexer, which predates the submitted ones.
+    Backfilled(HistoryEntry),
+    Status {
+        tx_hash: TxHash,
+        status: TransactionStatus,
+    },
+}
+
+/// Transactions known to the server, oldest first, along with the log they are persisted in.
+#[derive(Debug)]
+pub struct History {
+    log: File,
+    backfilled_entries: Vec<HistoryEntry>,
+    entries: Vec<HistoryEntry>,
+    /// Identities already backfilled from the indexer by this process.
+    backfilled: BTreeSet<String>,
+}
+
+impl History {
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+        };
+        let mut log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("opening {}", path.display()))?;
+        // Only the last line can be torn, by a crash while it was written. Records appended
+        // from now on start on a line of their own.
+        if !content.is_empty() && !content.ends_with('\n') {
+            log.write_all(b"\n")?;
+        }
+
+        let mut history = History {
+            log,
+            backfilled_entries: vec![],
+            entries: vec![],
+            backfilled: BTreeSet::new(),
+        };
+        for (i, line) in content.lines().enumerate() {
+            match serde_json::from_str(line) {
+                Ok(record) => history.apply(record),
+                Err(e) => warn!("Skipping line {} of {}: {e}", i + 1, path.display()),
+            }
+        }
+        Ok(history)
+    }
+
+    fn apply(&mut self, record: Record) {
+        match record {
+            Record::Submitted(entry) => self.entries.push(entry),
+            Record::Backfilled(entry) => self.backfilled_entries.push(entry),
+            Record::Status { tx_hash, status } => {
+                for entry in self.all_mut().filter(|e| e.tx_hash == tx_hash) {
+                    entry.status = status.clone();
+                }
+            }
+        }
+    }
+
+    /// Appends `records` to the log, then applies them.
+    fn append(&mut self, records: Vec<Record>) -> Result<()> {
+        let mut lines = vec![];
+        for record in &records {
+            serde_json::to_writer(&mut lines, record)?;
+            lines.push(b'\n');
+        }
+        self.log
+            .write_all(&lines)
+            .context("appending to history log")?;
+        for record in records {
+            self.apply(record);
+        }
+        Ok(())
+    }
+
+    fn all(&self) -> impl DoubleEndedIterator<Item = &HistoryEntry> {
+        self.backfilled_entries.iter().chain(&self.entries)
+    }
+
+    fn all_mut(&mut self) -> impl Iterator<Item = &mut HistoryEntry> {
+        self.backfilled_entries.iter_mut().chain(&mut self.entries)
+    }
+
+    pub fn record(&mut self, entries: Vec<HistoryEntry>) -> Result<()> {
+        self.append(entries.into_iter().map(Record::Submitted).collect())
+    }
+
+    fn involves(entry: &HistoryEntry, identity: &str) -> bool {
+        entry.identity.0 == identity || entry.counterparty.as_deref() == Some(identity)
+    }
+
+    /// Entries of `identity` matching the query, newest first, along with the total count.
+    fn query(&self, identity: &str, query: &HistoryQuery) -> (Vec<HistoryEntry>, usize) {
+        let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+        let matching: Vec<_> = self
+            .all()
+            .rev()
+            .filter(|entry| Self::involves(entry, identity))
+            .filter(|entry| {
+                query
+                    .token
+                    .as_ref()
+                    .map_or(true, |t| entry.tokens.contains(t))
+            })
+            .filter(|entry| query.operation.map_or(true, |op| entry.operation == op))
+            .collect();
+        let total = matching.len();
+        let page = matching
+            .into_iter()
+            .skip(query.offset)
+            .take(limit)
+            .cloned()
+            .collect();
+        (page, total)
+    }
+
+    /// Settled swaps between `token_a` and `token_b` submitted since `since`, in seconds since the
+    /// unix epoch.
+    pub fn swap_volume(&self, token_a: &str, token_b: &str, since: u64) -> SwapVolume {
+        let mut volume = SwapVolume::default();
+        for entry in &self.entries {
+            let [first, second] = entry.tokens.as_slice() else {
+                continue;
+            };
+            if entry.operation != HistoryOperation::Swap
+                || entry.submitted_at.is_none_or(|at| at < since)
+                || entry.status != TransactionStatus::Success
+                || !(first.0 == token_a && second.0 == token_b
+                    || first.0 == token_b && second.0 == token_a)
+            {
+                continue;
+            }
+            volume.swaps += 1;
+            for (token, amount) in entry.tokens.iter().zip(&entry.amounts) {
+                if token.0 == token_a {
+                    volume.amount_a = volume.amount_a.saturating_add(*amount);
+                } else {
+                    volume.amount_b = volume.amount_b.saturating_add(*amount);
+                }
+            }
+        }
+        volume
+    }
+
+    /// Entries of transaction `tx_hash`, one per operation.
+    pub fn transaction(&self, tx_hash: &TxHash) -> Vec<HistoryEntry> {
+        self.all()
+            .filter(|entry| &entry.tx_hash == tx_hash)
+            .cloned()
+            .collect()
+    }
+
+    /// Sets the status of the entries of transaction `tx_hash`, if any.
+    pub fn update_status(&mut self, tx_hash: TxHash, status: TransactionStatus) -> Result<()> {
+        if !self
+            .all()
+            .any(|entry| entry.tx_hash == tx_hash && entry.status != status)
+        {
+            return Ok(());
+        }
+        self.append(vec![Record::Status { tx_hash, status }])
+    }
+}
+
+fn is_final(status: &TransactionStatus) -> bool {
+    matches!(
+        status,
+        TransactionStatus::Success | TransactionStatus::Failure | TransactionStatus::TimedOut
+    )
+}
+
+/// History entry of an operation of `identity` in transaction `tx_hash`. `amount_out` is the
+/// quoted amount received by swaps.
+pub fn entry(
+    tx_hash: &TxHash,
+    identity: &Identity,
+    operation: &Operation,
+    amount_out: Option<u128>,
+) -> HistoryEntry {
+    let (operation, counterparty, tokens, amounts) = match operation {
+        Operation::Transfer {
+            recipient,
+            token,
+            amount,
+        } => (
+            HistoryOperation::Transfer,
+            Some(recipient.clone()),
+            vec![token.clone()],
+            vec![*amount],
+        ),
+        Operation::TransferFrom {
+            owner,
+            token,
+            amount,
+            ..
+        } => (
+            HistoryOperation::TransferFrom,
+            Some(owner.clone()),
+            vec![token.clone()],
+            vec![*amount],
+        ),
+        Operation::Approve {
+            spender,
+            token,
+            amount,
+        } => (
+            HistoryOperation::Approve,
+            Some(spender.clone()),
+            vec![token.clone()],
+            vec![*amount],
+        ),
+        Operation::Swap {
+            token_a,
+            token_b,
+            amount,
+        } => (
+            HistoryOperation::Swap,
+            None,
+            vec![token_a.clone(), token_b.clone()],
+            [Some(*amount), amount_out].into_iter().flatten().collect(),
+        ),
+        Operation::Register => (HistoryOperation::Register, None, vec![], vec![]),
+    };
+    HistoryEntry {
+        tx_hash: tx_hash.clone(),
+        operation,
+        identity: identity.clone(),
+        counterparty,
+        tokens,
+        amounts,
+        status: TransactionStatus::Sequenced,
+        submitted_at: SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .ok()
+            .map(|elapsed| elapsed.as_secs()),
+    }
+}
+
+pub fn record(history: &SyncMutex<History>, entries: Vec<HistoryEntry>) {
+    if let Err(e) = history.lock().unwrap().record(entries) {
+        error!("Failed to record transaction history: {e:#}");
+    }
+}
+
+/// Page of the history of `identity`, refreshing the status of its unsettled transactions from
+/// the indexer, at most [`MAX_STATUS_REFRESH`] of them concurrently.
+pub async fn history(ctx: &RouterCtx, identity: &str, query: &HistoryQuery) -> HistoryResponse {
+    if ctx.conf.history.backfill {
+        backfill(ctx, identity).await;
+    }
+
+    let (mut entries, total) = ctx.history.lock().unwrap().query(identity, query);
+
+    let mut unsettled: Vec<TxHash> = vec![];
+    for entry in entries.iter().filter(|entry| !is_final(&entry.status)) {
+        if !unsettled.contains(&entry.tx_hash) && unsettled.len() < MAX_STATUS_REFRESH {
+            unsettled.push(entry.tx_hash.clone());
+        }
+    }
+    let mut fetches = JoinSet::new();
+    for tx_hash in unsettled {
+        let indexer = ctx.indexer.clone();
+        fetches.spawn(async move {
+            let status = indexer
+                .get_transaction_with_hash(&tx_hash)
+                .await
+                .map(|tx| tx.transaction_status);
+            (tx_hash, status)
+        });
+    }
+    while let Some(fetched) = fetches.join_next().await {
+        let (tx_hash, status) = match fetched {
+            Ok((tx_hash, Ok(status))) => (tx_hash, status),
+            Ok((tx_hash, Err(e))) => {
+                warn!("Failed to fetch status of {tx_hash}: {e:#}");
+                continue;
+            }
+            Err(e) => {
+                error!("Status fetch panicked: {e}");
+                continue;
+            }
+        };
+        for entry in entries.iter_mut().filter(|e| e.tx_hash == tx_hash) {
+            entry.status = status.clone();
+        }
+        if let Err(e) = ctx.history.lock().unwrap().update_status(tx_hash, status) {
+            error!("Failed to update transaction history: {e:#}");
+        }
+    }
+
+    HistoryResponse { entries, total }
+}
+
+/// Adds the token transactions sent or received by `identity` known to the indexer but not to
+/// the history, once per identity and process. An identity whose backfill fails is tried again by its next
+/// request.
+async fn backfill(ctx: &RouterCtx, identity: &str) {
+    if ctx.history.lock().unwrap().backfilled.contains(identity) {
+        return;
+    }
+
+    let mut entries: Vec<HistoryEntry> = vec![];
+    for token in TOKENS.map(ContractName::from) {
+        if let Err(e) = backfill_token(&ctx.indexer, &token, identity, &mut entries).await {
+            warn!("Failed to backfill {token} history of {identity}: {e:#}");
+            return;
+        }
+    }
+
+    let mut history = ctx.history.lock().unwrap();
+    if !history.backfilled.insert(identity.to_string()) {
+        return;
+    }
+    entries.retain(|entry| !history.all().any(|e| e.tx_hash == entry.tx_hash));
+    if let Err(e) = history.append(entries.into_iter().map(Record::Backfilled).collect()) {
+        error!("Failed to record transaction history: {e:#}");
+    }
+}
+
+async fn backfill_token(
+    indexer: &IndexerApiHttpClient,
+    token: &ContractName,
+    identity: &str,
+    entries: &mut Vec<HistoryEntry>,
+) -> Result<()> {
+    let txs = indexer.get_blob_transactions_by_contract(token).await?;
+    for tx in txs {
+        let actions: Vec<ERC20Action> = tx
+            .blobs
+            .iter()
+            .filter(|blob| blob.contract_name == token.0)
+            .filter_map(|blob| {
+                StructuredBlobData::<ERC20Action>::try_from(BlobData(blob.data.clone())).ok()
+            })
+            .map(|data| data.parameters)
+            .collect();
+        entries.extend(backfilled_entry(
+            identity,
+            tx.tx_hash.0,
+            tx.transaction_status,
+            &tx.identity,
+            token,
+            &actions,
+        ));
+    }
+    Ok(())
+}
+
+/// Entry of a `token` transaction sent by `sender`, if `identity` sent it or is the recipient of
+/// one of its `actions`.
+fn backfilled_entry(
+    identity: &str,
+    tx_hash: TxHash,
+    status: TransactionStatus,
+    sender: &str,
+    token: &ContractName,
+    actions: &[ERC20Action],
+) -> Option<HistoryEntry> {
+    let received = actions.iter().find_map(|action| match action {
+        ERC20Action::Transfer { recipient, amount }
+        | ERC20Action::TransferFrom {
+            recipient, amount, ..
+        } if recipient == identity => Some(*amount),
+        _ => None,
+    });
+    if sender != identity && received.is_none() {
+        return None;
+    }
+    Some(HistoryEntry {
+        tx_hash,
+        operation: HistoryOperation::External,
+        identity: Identity(sender.to_string()),
+        counterparty: received.map(|_| identity.to_string()),
+        tokens: vec![token.clone()],
+        amounts: received.into_iter().collect(),
+        status,
+        submitted_at: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open(name: &str) -> (History, std::path::PathBuf) {
+        let path = std::env::temp_dir().join(format!(
+            "hyleoof-history-{name}-{}.jsonl",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        (History::open(&path).unwrap(), path)
+    }
+
+    fn transfer(tx_hash: &str, identity: &str, recipient: &str, token: &str) -> HistoryEntry {
+        entry(
+            &TxHash(tx_hash.to_string()),
+            &Identity(identity.to_string()),
+            &Operation::Transfer {
+                recipient: recipient.to_string(),
+                token: token.into(),
+                amount: 10,
+            },
+            None,
+        )
+    }
+
+    fn query(token: Option<&str>, offset: usize, limit: Option<usize>) -> HistoryQuery {
+        HistoryQuery {
+            token: token.map(ContractName::from),
+            operation: None,
+            offset,
+            limit,
+        }
+    }
+
+    fn tx_hashes(entries: &[HistoryEntry]) -> Vec<&str> {
+        entries
+            .iter()
+            .map(|entry| entry.tx_hash.0.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn queries_return_the_entries_of_an_identity_newest_first() {
+        let (mut history, path) = open("query");
+        history
+            .record(vec![
+                transfer("0x1", "alice", "bob", "hyllar"),
+                transfer("0x2", "bob", "alice", "hyllar2"),
+                transfer("0x3", "bob", "carol", "hyllar"),
+                transfer("0x4", "alice", "carol", "hyllar"),
+            ])
+            .unwrap();
+
+        let (entries, total) = history.query("alice", &query(None, 0, None));
+        assert_eq!(tx_hashes(&entries), ["0x4", "0x2", "0x1"]);
+        assert_eq!(total, 3);
+
+        let (entries, total) = history.query("alice", &query(Some("hyllar"), 1, Some(1)));
+        assert_eq!(tx_hashes(&entries), ["0x1"]);
+        assert_eq!(total, 2);
+
+        let (entries, _) = history.query("carol", &query(None, 0, None));
+        assert_eq!(tx_hashes(&entries), ["0x4", "0x3"]);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn status_updates_survive_a_restart() {
+        let (mut history, path) = open("status");
+        history
+            .record(vec![transfer("0x1", "alice", "bob", "hyllar")])
+            .unwrap();
+        let tx_hash = TxHash("0x1".to_string());
+        history
+            .update_status(tx_hash.clone(), TransactionStatus::Success)
+            .unwrap();
+        // Unchanged statuses are not appended again.
+        history
+            .update_status(tx_hash.clone(), TransactionStatus::Success)
+            .unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 2);
+
+        let history = History::open(&path).unwrap();
+        assert_eq!(
+            history.transaction(&tx_hash)[0].status,
+            TransactionStatus::Success
+        );
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn backfill_matches_senders_and_recipients() {
+        let token = ContractName::from("hyllar");
+        let actions = [ERC20Action::Transfer {
+            recipient: "bob".to_string(),
+            amount: 10,
+        }];
+        let backfilled = |identity| {
+            backfilled_entry(
+                identity,
+                TxHash("0x1".to_string()),
+                TransactionStatus::Success,
+                "alice",
+                &token,
+                &actions,
+            )
+        };
+
+        let sent = backfilled("alice").unwrap();
+        assert_eq!(sent.identity.0, "alice");
+        assert_eq!(sent.counterparty, None);
+
+        let received = backfilled("bob").unwrap();
+        assert_eq!(received.identity.0, "alice");
+        assert_eq!(received.counterparty.as_deref(), Some("bob"));
+        assert_eq!(received.amounts, [10]);
+
+        assert!(backfilled("carol").is_none());
+    }
+}
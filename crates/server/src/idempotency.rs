@@ -0,0 +1,439 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+    sync::{Arc, Mutex as SyncMutex},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, Result};
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Request, State},
+    http::{HeaderName, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use hyleoof_api::TxHashResponse;
+use sdk::TxHash;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{error, info};
+use utoipa::IntoParams;
+
+use crate::{
+    store::{load_json, save_json},
+    utils::AppError,
+};
+
+const IDEMPOTENCY_KEY: HeaderName = HeaderName::from_static("idempotency-key");
+const IDEMPOTENT_REPLAYED: HeaderName = HeaderName::from_static("idempotent-replayed");
+
+/// Largest request body accepted by the idempotency layer.
+const MAX_BODY_BYTES: usize = 64 * 1024;
+
+/// Header accepted by the endpoints submitting transactions.
+#[derive(IntoParams)]
+#[into_params(parameter_in = Header)]
+#[allow(dead_code)]
+pub struct IdempotencyKey {
+    /// Retrying a request with the same key returns the original transaction hash instead of
+    /// submitting it again.
+    #[param(rename = "Idempotency-Key")]
+    idempotency_key: Option<String>,
+}
+
+/// Transactions submitted with an `Idempotency-Key`, persisted so that retries after a
+/// restart are not submitted twice either.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct IdempotencyStore {
+    #[serde(skip)]
+    path: PathBuf,
+    #[serde(skip)]
+    ttl: Duration,
+    entries: BTreeMap<String, Entry>,
+    /// Keys whose request is being handled.
+    #[serde(skip)]
+    in_flight: BTreeSet<String>,
+    /// Keys whose transaction is being sent to the node, kept in flight even when their request
+    /// is dropped so that a retry cannot submit it a second time.
+    #[serde(skip)]
+    submitting: BTreeSet<String>,
+    /// Held while writing the store, so that snapshots reach the disk in order.
+    #[serde(skip)]
+    saving: Arc<tokio::sync::Mutex<()>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    /// Hash of the route and body of the original request.
+    fingerprint: String,
+    tx_hash: TxHash,
+    /// Seconds since the unix epoch.
+    created_at: u64,
+}
+
+enum Begin {
+    New,
+    Replay(TxHash),
+    InFlight,
+    Mismatch,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+impl IdempotencyStore {
+    pub fn open(path: PathBuf, ttl: Duration) -> Result<Self> {
+        let mut store: IdempotencyStore = load_json(&path)?;
+        store.path = path;
+        store.ttl = ttl;
+        store.expire();
+        Ok(store)
+    }
+
+    fn expire(&mut self) {
+        let oldest = now().saturating_sub(self.ttl.as_secs());
+        self.entries.retain(|_, entry| entry.created_at >= oldest);
+    }
+
+    fn begin(&mut self, key: &str, fingerprint: &str) -> Begin {
+        self.expire();
+        if let Some(entry) = self.entries.get(key) {
+            return if entry.fingerprint == fingerprint {
+                Begin::Replay(entry.tx_hash.clone())
+            } else {
+                Begin::Mismatch
+            };
+        }
+        if !self.in_flight.insert(key.to_string()) {
+            return Begin::InFlight;
+        }
+        Begin::New
+    }
+
+    fn submit(&mut self, key: &str) {
+        self.submitting.insert(key.to_string());
+    }
+
+    fn complete(&mut self, key: &str, fingerprint: String, tx_hash: TxHash) {
+        self.entries.insert(
+            key.to_string(),
+            Entry {
+                fingerprint,
+                tx_hash,
+                created_at: now(),
+            },
+        );
+        self.fail(key);
+    }
+
+    fn fail(&mut self, key: &str) {
+        self.submitting.remove(key);
+        self.in_flight.remove(key);
+    }
+
+    /// Releases a key whose request ended without a transaction being submitted.
+    fn abort(&mut self, key: &str) {
+        if !self.submitting.contains(key) {
+            self.in_flight.remove(key);
+        }
+    }
+}
+
+/// Writes `store` to disk from a blocking task, without holding its lock during the write.
+async fn save(store: &SyncMutex<IdempotencyStore>) -> Result<()> {
+    let saving = store.lock().unwrap().saving.clone();
+    let _saving = saving.lock().await;
+    let (path, snapshot) = {
+        let store = store.lock().unwrap();
+        (store.path.clone(), serde_json::to_value(&*store)?)
+    };
+    tokio::task::spawn_blocking(move || save_json(&path, &snapshot)).await?
+}
+
+tokio::task_local! {
+    /// Key of the request being handled by [`layer`], picked up by [`Submission::start`].
+    static PENDING: Pending;
+}
+
+#[derive(Clone)]
+struct Pending {
+    store: Arc<SyncMutex<IdempotencyStore>>,
+    key: String,
+    fingerprint: String,
+}
+
+/// `Idempotency-Key` of a transaction being sent, resolved by the task sending it rather than
+/// by the request, which may be dropped once the transaction is on its way to the node.
+pub struct Submission(Option<Pending>);
+
+impl Submission {
+    /// Marks the key of the request being handled, if it has one, as submitting.
+    pub fn start() -> Self {
+        let pending = PENDING.try_with(Pending::clone).ok();
+        if let Some(pending) = &pending {
+            pending.store.lock().unwrap().submit(&pending.key);
+        }
+        Submission(pending)
+    }
+
+    /// Records `tx_hash` as the transaction of the key, replayed to its retries.
+    pub async fn complete(self, tx_hash: &TxHash) {
+        let Some(Pending {
+            store,
+            key,
+            fingerprint,
+        }) = self.0
+        else {
+            return;
+        };
+        store
+            .lock()
+            .unwrap()
+            .complete(&key, fingerprint, tx_hash.clone());
+        if let Err(e) = save(&store).await {
+            error!("Failed to persist Idempotency-Key {key}: {e:#}");
+        }
+    }
+
+    /// Releases the key of a transaction the node refused, so that it can be retried.
+    pub fn fail(self) {
+        if let Some(pending) = self.0 {
+            pending.store.lock().unwrap().fail(&pending.key);
+        }
+    }
+}
+
+/// Releases the key of a request when its handling ends, including when the client goes away
+/// and the handler future is dropped, unless its transaction is being submitted.
+struct InFlight {
+    store: Arc<SyncMutex<IdempotencyStore>>,
+    key: String,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        if let Ok(mut store) = self.store.lock() {
+            store.abort(&self.key);
+        }
+    }
+}
+
+fn fingerprint(path: &str, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(path.as_bytes());
+    hasher.update([0]);
+    hasher.update(body);
+    hex::encode(hasher.finalize())
+}
+
+/// Middleware of the endpoints submitting transactions: a request carrying an already used
+/// `Idempotency-Key` gets the transaction hash of the first one instead of being handled again.
+pub async fn layer(
+    State(store): State<Arc<SyncMutex<IdempotencyStore>>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let Some(key) = request.headers().get(&IDEMPOTENCY_KEY) else {
+        return Ok(next.run(request).await);
+    };
+    let key = key
+        .to_str()
+        .ok()
+        .filter(|key| !key.is_empty() && key.len() <= 255)
+        .ok_or_else(|| AppError(StatusCode::BAD_REQUEST, anyhow!("invalid Idempotency-Key")))?
+        .to_string();
+
+    let (parts, body) = request.into_parts();
+    let body = to_bytes(body, MAX_BODY_BYTES)
+        .await
+        .map_err(|e| AppError(StatusCode::BAD_REQUEST, e.into()))?;
+    let fingerprint = fingerprint(parts.uri.path(), &body);
+
+    let begin = store.lock().unwrap().begin(&key, &fingerprint);
+    let _in_flight = match begin {
+        Begin::New => InFlight {
+            store: store.clone(),
+            key: key.clone(),
+        },
+        Begin::Replay(tx_hash) => {
+            info!("Replaying transaction {tx_hash} for Idempotency-Key {key}");
+            let mut response = Json(TxHashResponse { tx_hash }).into_response();
+            response
+                .headers_mut()
+                .insert(IDEMPOTENT_REPLAYED, HeaderValue::from_static("true"));
+            return Ok(response);
+        }
+        Begin::InFlight => {
+            return Err(AppError(
+                StatusCode::CONFLICT,
+                anyhow!("a request with this Idempotency-Key is in progress"),
+            ))
+        }
+        Begin::Mismatch => {
+            return Err(AppError(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                anyhow!("Idempotency-Key already used for a different request"),
+            ))
+        }
+    };
+
+    let pending = Pending {
+        store,
+        key,
+        fingerprint,
+    };
+    Ok(PENDING
+        .scope(
+            pending,
+            next.run(Request::from_parts(parts, Body::from(body))),
+        )
+        .await)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use axum::{middleware, routing::post, Router};
+    use tokio::sync::Notify;
+    use tower::ServiceExt;
+    use tower_http::timeout::TimeoutLayer;
+
+    use super::*;
+
+    fn store(ttl: Duration) -> IdempotencyStore {
+        IdempotencyStore {
+            ttl,
+            ..Default::default()
+        }
+    }
+
+    fn tx_hash() -> TxHash {
+        TxHash("0xabc".to_string())
+    }
+
+    #[test]
+    fn a_completed_key_replays_its_transaction() {
+        let mut store = store(Duration::from_secs(60));
+        assert!(matches!(store.begin("key", "body"), Begin::New));
+        store.complete("key", "body".to_string(), tx_hash());
+        store.abort("key");
+
+        assert!(matches!(store.begin("key", "body"), Begin::Replay(hash) if hash == tx_hash()));
+        assert!(matches!(store.begin("key", "other"), Begin::Mismatch));
+    }
+
+    #[test]
+    fn a_key_in_flight_is_refused_until_aborted() {
+        let mut store = store(Duration::from_secs(60));
+        assert!(matches!(store.begin("key", "body"), Begin::New));
+        assert!(matches!(store.begin("key", "body"), Begin::InFlight));
+
+        store.abort("key");
+        assert!(matches!(store.begin("key", "body"), Begin::New));
+    }
+
+    #[test]
+    fn the_in_flight_guard_releases_its_key() {
+        let store = Arc::new(SyncMutex::new(store(Duration::from_secs(60))));
+        assert!(matches!(
+            store.lock().unwrap().begin("key", "body"),
+            Begin::New
+        ));
+        drop(InFlight {
+            store: store.clone(),
+            key: "key".to_string(),
+        });
+        assert!(matches!(
+            store.lock().unwrap().begin("key", "body"),
+            Begin::New
+        ));
+    }
+
+    #[test]
+    fn keys_expire_after_the_ttl() {
+        let mut store = store(Duration::from_secs(60));
+        store.complete("key", "body".to_string(), tx_hash());
+        store.entries.get_mut("key").unwrap().created_at = now() - 61;
+        store.abort("key");
+
+        assert!(matches!(store.begin("key", "other"), Begin::New));
+        assert!(store.entries.is_empty());
+    }
+
+    #[test]
+    fn a_submitting_key_is_not_released_by_its_request() {
+        let mut store = store(Duration::from_secs(60));
+        assert!(matches!(store.begin("key", "body"), Begin::New));
+        store.submit("key");
+        store.abort("key");
+        assert!(matches!(store.begin("key", "body"), Begin::InFlight));
+
+        store.fail("key");
+        assert!(matches!(store.begin("key", "body"), Begin::New));
+    }
+
+    #[tokio::test]
+    async fn a_request_dropped_after_sending_is_replayed_instead_of_sent_again() {
+        let path =
+            std::env::temp_dir().join(format!("hyleoof-idempotency-{}.json", std::process::id()));
+        let store = Arc::new(SyncMutex::new(
+            IdempotencyStore::open(path.clone(), Duration::from_secs(60)).unwrap(),
+        ));
+        let node = Arc::new(Notify::new());
+        let sends = Arc::new(AtomicUsize::new(0));
+
+        // Sends like `HyleOofCtx::send`: from a task of its own, answered when `node` is notified.
+        let handler = {
+            let node = node.clone();
+            let sends = sends.clone();
+            move || {
+                let (node, sends) = (node.clone(), sends.clone());
+                async move {
+                    let submission = Submission::start();
+                    sends.fetch_add(1, Ordering::SeqCst);
+                    let tx_hash = tokio::spawn(async move {
+                        node.notified().await;
+                        submission.complete(&tx_hash()).await;
+                        tx_hash()
+                    })
+                    .await
+                    .unwrap();
+                    Json(TxHashResponse { tx_hash })
+                }
+            }
+        };
+        let router = Router::new()
+            .route("/send", post(handler))
+            .route_layer(middleware::from_fn_with_state(store.clone(), layer))
+            .layer(TimeoutLayer::new(Duration::from_millis(50)));
+        let request = || {
+            axum::http::Request::post("/send")
+                .header(IDEMPOTENCY_KEY, "key")
+                .body(Body::from("{}"))
+                .unwrap()
+        };
+
+        let response = router.clone().oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::REQUEST_TIMEOUT);
+        let response = router.clone().oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        node.notify_one();
+        while !store.lock().unwrap().entries.contains_key("key") {
+            tokio::task::yield_now().await;
+        }
+        let response = router.clone().oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().contains_key(IDEMPOTENT_REPLAYED));
+        assert_eq!(sends.load(Ordering::SeqCst), 1);
+
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,148 @@
+use anyhow::{anyhow, Result};
+use client_sdk::transaction_builder::ProvableBlobTx;
+use sdk::ContractName;
+
+use crate::{conf::Conf, States};
+
+/// Authorizes transactions for the identities of one identity contract, i.e. the identities
+/// ending with `.<contract>`.
+pub trait IdentityProvider: Send + Sync {
+    /// Deployed name of the identity contract.
+    fn contract_name(&self) -> &ContractName;
+
+    fn is_registered(&self, states: &States, identity: &str) -> bool;
+
+    /// Number of times a registered identity was verified, when the contract keeps track.
+    fn nonce(&self, _states: &States, _identity: &str) -> Option<u32> {
+        None
+    }
+
+    /// Adds the blob registering the identity of `transaction`, authenticated by `secret`.
+    fn register(&self, transaction: &mut ProvableBlobTx, secret: String) -> Result<()>;
+
+    /// Adds the blob proving that the sender of `transaction` owns its identity.
+    fn verify(
+        &self,
+        transaction: &mut ProvableBlobTx,
+        states: &States,
+        secret: String,
+    ) -> Result<()>;
+
+    /// Whether the contract is able to replace the secret of an identity.
+    fn supports_rotation(&self) -> bool {
+        false
+    }
+}
+
+/// Password based identities of the hydentity contract, whose state is kept in [`States`].
+pub struct HydentityProvider {
+    contract_name: ContractName,
+}
+
+impl IdentityProvider for HydentityProvider {
+    fn contract_name(&self) -> &ContractName {
+        &self.contract_name
+    }
+
+    fn is_registered(&self, states: &States, identity: &str) -> bool {
+        states.hydentity.get_identity_info(identity).is_ok()
+    }
+
+    fn nonce(&self, states: &States, identity: &str) -> Option<u32> {
+        let info = states.hydentity.get_identity_info(identity).ok()?;
+        let info: serde_json::Value = serde_json::from_str(&info).ok()?;
+        info["nonce"]
+            .as_u64()
+            .and_then(|nonce| nonce.try_into().ok())
+    }
+
+    fn register(&self, transaction: &mut ProvableBlobTx, secret: String) -> Result<()> {
+        hydentity::client::register_identity(transaction, self.contract_name.clone(), secret)
+    }
+
+    fn verify(
+        &self,
+        transaction: &mut ProvableBlobTx,
+        states: &States,
+        secret: String,
+    ) -> Result<()> {
+        hydentity::client::verify_identity(
+            transaction,
+            self.contract_name.clone(),
+            &states.hydentity,
+            secret,
+        )
+    }
+}
+
+/// Provider of the identity contract named `contract`, when the server has one. Providers are
+/// registered by listing their contract in `identities.providers`.
+pub fn provider(contract: &str) -> Option<Box<dyn IdentityProvider>> {
+    match contract {
+        // Its state is the `hydentity` one of `States`.
+        "hydentity" => Some(Box::new(HydentityProvider {
+            contract_name: contract.into(),
+        })),
+        _ => None,
+    }
+}
+
+/// Identity providers of the server, picked by the suffix of the identities.
+pub struct IdentityProviders {
+    /// The first one is the default, used for names given without a suffix.
+    providers: Vec<Box<dyn IdentityProvider>>,
+}
+
+impl IdentityProviders {
+    /// Providers of the `identities.providers` contracts, in that order.
+    pub fn new(conf: &Conf) -> Self {
+        IdentityProviders {
+            providers: conf
+                .identities
+                .providers
+                .iter()
+                .filter_map(|contract| provider(contract))
+                .collect(),
+        }
+    }
+
+    pub fn default_provider(&self) -> &dyn IdentityProvider {
+        self.providers[0].as_ref()
+    }
+
+    /// Provider of the identity contract named `contract`.
+    pub fn get(&self, contract: &str) -> Option<&dyn IdentityProvider> {
+        self.providers
+            .iter()
+            .find(|provider| provider.contract_name().0 == contract)
+            .map(|provider| provider.as_ref())
+    }
+
+    /// Provider of an identity like `bob.<contract>`.
+    pub fn for_identity(&self, identity: &str) -> Result<&dyn IdentityProvider> {
+        identity
+            .split_once('.')
+            .and_then(|(_, contract)| self.get(contract))
+            .ok_or_else(|| anyhow!("no identity provider for {identity}"))
+    }
+
+    pub fn is_registered(&self, states: &States, identity: &str) -> bool {
+        self.for_identity(identity)
+            .is_ok_and(|provider| provider.is_registered(states, identity))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn providers_are_registered_from_the_configuration() {
+        let conf = Conf::defaults();
+        let providers = IdentityProviders::new(&conf);
+        assert_eq!(providers.default_provider().contract_name().0, "hydentity");
+        assert!(providers.for_identity("bob.hydentity").is_ok());
+        assert!(providers.for_identity("bob.other").is_err());
+        assert!(provider("other").is_none());
+    }
+}
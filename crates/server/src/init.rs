@@ -1,207 +1,688 @@
-use std::{collections::BTreeMap, sync::Arc, time::Duration};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
-use anyhow::{bail, Result};
+use amm::AmmState;
+use anyhow::{anyhow, bail, Result};
 use client_sdk::{
     rest_client::{IndexerApiHttpClient, NodeApiHttpClient},
     transaction_builder::{ProvableBlobTx, TxExecutorBuilder},
 };
-use hyllar::client::metadata::HYLLAR_ELF;
+use hyllar::HyllarTokenContract;
 use risc0_zkvm::compute_image_id;
 use sdk::{
-    api::APIRegisterContract, erc20::ERC20, BlobTransaction, ContractName, Digestable, ProgramId,
-    StateDigest,
+    api::{APIRegisterContract, TransactionStatus},
+    erc20::ERC20,
+    BlobTransaction, ContractName, Hashed, ProgramId, StateDigest, TxHash,
 };
 use tokio::time::timeout;
-use tracing::{debug, info};
+use tracing::{info, warn};
 
-use crate::{task_manager::Prover, HyleOofCtx, States};
+use crate::{
+    conf::Conf,
+    fetch_states,
+    genesis::{ContractKind, ContractManifest, Genesis, MismatchPolicy},
+    identity::IdentityProviders,
+    journal::{Journal, StepStatus},
+    task_manager::Prover,
+    HyleOofCtx,
+};
 
-pub async fn init_node(
-    node: Arc<NodeApiHttpClient>,
-    indexer: Arc<IndexerApiHttpClient>,
-) -> Result<()> {
-    init_amm(&node, &indexer).await?;
-    init_hyllar2(&node, &indexer).await?;
-    init_hyllar(node, indexer).await?;
-    Ok(())
+/// Contracts of the genesis manifest found or registered on-chain, and whether they are served
+/// read-only.
+#[derive(Debug, Clone, Default)]
+pub struct Deployment {
+    /// Whether each contract is read-only, keyed by contract name.
+    contracts: BTreeMap<String, bool>,
 }
 
-async fn init_amm(node: &NodeApiHttpClient, indexer: &IndexerApiHttpClient) -> Result<()> {
-    match indexer.get_indexer_contract(&"amm".into()).await {
-        Ok(contract) => {
-            let image_id = hex::encode(compute_image_id(amm::client::metadata::AMM_ELF)?);
-            let program_id = hex::encode(contract.program_id.as_slice());
-            if program_id != image_id {
-                bail!(
-                    "Invalid AMM contract image_id. On-chain version is {program_id}, expected {image_id}",
-                );
+impl Deployment {
+    fn insert(&mut self, contract: &ContractName, read_only: bool) {
+        self.contracts.insert(contract.0.clone(), read_only);
+    }
+
+    /// Whether `contract` is declared in the genesis manifest.
+    pub fn contains(&self, contract: &str) -> bool {
+        self.contracts.contains_key(contract)
+    }
+
+    pub fn is_read_only(&self, name: &ContractName) -> bool {
+        self.contracts.get(&name.0).copied().unwrap_or(false)
+    }
+}
+
+/// A single step of the bootstrap, as computed by [`plan`].
+#[derive(Debug)]
+pub enum Action {
+    Register {
+        contract: ContractName,
+        image_id: String,
+    },
+    Fund {
+        token: ContractName,
+        recipient: String,
+        amount: u128,
+    },
+    Approve {
+        token: ContractName,
+        spender: String,
+        amount: u128,
+    },
+    /// Registration sent by a previous run whose contract has not shown up yet.
+    Registering {
+        contract: ContractName,
+        tx_hash: TxHash,
+    },
+    UpToDate {
+        contract: ContractName,
+    },
+    /// Seeds sent and proved by a previous run that have not settled yet.
+    Pending {
+        token: ContractName,
+        tx_hash: TxHash,
+    },
+    ReadOnly {
+        contract: ContractName,
+        program_id: String,
+        image_id: String,
+    },
+    Mismatch {
+        contract: ContractName,
+        reason: String,
+    },
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Register { contract, image_id } => {
+                write!(f, "register   {contract} (image id {image_id})")
             }
-            info!("✅ AMM contract is up to date");
-        }
-        Err(_) => {
-            info!("🚀 Registering AMM contract");
-            let image_id = hex::encode(compute_image_id(amm::client::metadata::AMM_ELF)?);
-            node.register_contract(&APIRegisterContract {
-                verifier: "risc0".into(),
-                program_id: ProgramId(hex::decode(image_id)?),
-                state_digest: amm::AmmState::new(BTreeMap::from([(
-                    amm::UnorderedTokenPair::new("hyllar".to_string(), "hyllar2".to_string()),
-                    (1_000_000_000, 1_000_000_000),
-                )]))
-                .as_digest(),
-                contract_name: "amm".into(),
-            })
-            .await?;
-            wait_contract_state(indexer, &"amm".into()).await?;
+            Action::Fund {
+                token,
+                recipient,
+                amount,
+            } => write!(f, "fund       {recipient} with {amount} {token}"),
+            Action::Approve {
+                token,
+                spender,
+                amount,
+            } => write!(f, "approve    {spender} to spend {amount} {token}"),
+            Action::Registering { contract, tx_hash } => write!(
+                f,
+                "pending    {contract} registration in transaction {tx_hash}"
+            ),
+            Action::UpToDate { contract } => write!(f, "up-to-date {contract}"),
+            Action::Pending { token, tx_hash } => {
+                write!(f, "pending    {token} seeds in transaction {tx_hash}")
+            }
+            Action::ReadOnly {
+                contract,
+                program_id,
+                image_id,
+            } => write!(
+                f,
+                "read-only  {contract}: on-chain version is {program_id}, expected {image_id}"
+            ),
+            Action::Mismatch { contract, reason } => write!(f, "mismatch   {contract}: {reason}"),
         }
-    };
+    }
+}
 
-    Ok(())
+/// Actions to bring the chain in line with the genesis manifest, along with the contracts the
+/// server will find on-chain once they are applied.
+pub struct Plan {
+    pub actions: Vec<Action>,
+    pub deployment: Deployment,
 }
 
-async fn init_hyllar(
+pub async fn init_node(
     node: Arc<NodeApiHttpClient>,
     indexer: Arc<IndexerApiHttpClient>,
-) -> Result<()> {
-    match indexer.get_indexer_contract(&"hyllar".into()).await {
-        Ok(contract) => {
-            let image_id = hex::encode(compute_image_id(HYLLAR_ELF)?);
-            let program_id = hex::encode(contract.program_id.as_slice());
-            if program_id != image_id {
-                bail!(
-                    "Invalid Hyllar contract image_id. On-chain version is {program_id}, expected {image_id}",
-                );
+    prover: &Arc<Prover>,
+    conf: &Conf,
+    genesis: &Genesis,
+    journal: &mut Journal,
+) -> Result<Deployment> {
+    let Plan {
+        actions,
+        deployment,
+    } = plan(&indexer, conf, genesis, journal).await?;
+
+    let mismatches: Vec<_> = actions
+        .iter()
+        .filter_map(|action| match action {
+            Action::Mismatch { contract, reason } => Some(format!("{contract}: {reason}")),
+            _ => None,
+        })
+        .collect();
+    if !mismatches.is_empty() {
+        bail!("Cannot initialize contracts:\n{}", mismatches.join("\n"));
+    }
+
+    for action in &actions {
+        match action {
+            Action::Register { contract, image_id } => {
+                let manifest = genesis
+                    .contract(contract)
+                    .expect("planned contracts come from the manifest");
+                register_contract(&node, &indexer, conf, genesis, journal, manifest, image_id)
+                    .await?;
             }
-            info!("✅ Hyllar contract is up to date");
-
-            let contract = hyllar::HyllarTokenContract::init(
-                StateDigest(contract.state_digest).try_into()?,
-                "faucet.hydentity".into(),
-            );
-
-            if contract.balance_of("amm").is_err() {
-                info!("🚀 Initializing Hyllar contract state");
-
-                let executor = TxExecutorBuilder::new(States {
-                    hyllar: contract.state().clone(),
-                    hyllar2: indexer.fetch_current_state(&"hyllar2".into()).await?,
-                    hydentity: indexer.fetch_current_state(&"hydentity".into()).await?,
-                    amm: indexer.fetch_current_state(&"amm".into()).await?,
-                })
-                .build();
-                let mut app = HyleOofCtx {
-                    executor,
-                    client: node.clone(),
-                    prover: Arc::new(Prover::new(node.clone())),
-                    hydentity_cn: "hydentity".into(),
-                    amm_cn: "amm".into(),
-                };
-                let mut transaction = ProvableBlobTx::new("faucet.hydentity".into());
-
-                app.verify_identity(&mut transaction, "password".into())?;
-                app.transfer(
-                    &mut transaction,
-                    "hyllar".into(),
-                    "amm".into(),
-                    1_000_000_000,
-                )?;
-                app.approve(
-                    &mut transaction,
-                    "hyllar".into(),
-                    "amm".into(),
-                    1_000_000_000_000_000,
+            Action::Registering { contract, tx_hash } => {
+                info!("⏳ Resuming {contract} registration, waiting for {tx_hash}");
+                wait_contract_state(&indexer, contract, conf.bootstrap_timeout())
+                    .await
+                    .map_err(|_| {
+                        anyhow!("Contract {contract} did not show up in time, restart to resume")
+                    })?;
+                journal.record(
+                    &register_step(contract),
+                    tx_hash.clone(),
+                    StepStatus::Settled,
                 )?;
+            }
+            Action::UpToDate { contract } => info!("✅ {contract} contract is up to date"),
+            Action::Pending { token, tx_hash } => {
+                info!("⏳ Resuming {token} initialization, waiting for {tx_hash}");
+                settle_step(&indexer, conf, journal, &seed_step(token), tx_hash).await?;
+            }
+            Action::ReadOnly {
+                contract,
+                program_id,
+                image_id,
+            } => warn!(
+                "⚠️ {contract} contract is outdated (on-chain {program_id}, expected {image_id}), serving it read-only"
+            ),
+            Action::Fund { .. } | Action::Approve { .. } | Action::Mismatch { .. } => {}
+        }
+    }
 
-                let blob_tx =
-                    BlobTransaction::new(transaction.identity.clone(), transaction.blobs.clone());
+    // Seeds only go out once every contract is registered: building them fetches the states
+    // of all the contracts the server transacts with.
+    for contract in genesis.registration_order()? {
+        let token = &contract.name;
+        let seeds: Vec<_> = actions
+            .iter()
+            .filter(|action| match action {
+                Action::Fund { token: t, .. } | Action::Approve { token: t, .. } => t == token,
+                _ => false,
+            })
+            .collect();
+        if !seeds.is_empty() {
+            seed_token(
+                &node,
+                &indexer,
+                prover,
+                conf,
+                &deployment,
+                journal,
+                token,
+                &seeds,
+            )
+            .await?;
+        }
+    }
+
+    Ok(deployment)
+}
+
+/// Image id check of a genesis manifest contract, as reported by [`verify`].
+pub struct Verification {
+    pub contract: ContractName,
+    pub image_id: String,
+    pub status: VerificationStatus,
+}
 
-                let proof_tx_builder = app.executor.process(transaction)?;
+pub enum VerificationStatus {
+    Ok,
+    Mismatch {
+        program_id: String,
+        policy: MismatchPolicy,
+    },
+    Unregistered {
+        required: bool,
+    },
+}
 
-                let tx_hash = node.send_tx_blob(&blob_tx).await?;
+impl Verification {
+    /// Whether the server would refuse to start because of this contract.
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            self.status,
+            VerificationStatus::Mismatch {
+                policy: MismatchPolicy::Refuse,
+                ..
+            } | VerificationStatus::Unregistered { required: true }
+        )
+    }
+}
 
-                info!("🚀 Proving blobs for {tx_hash}");
+impl fmt::Display for Verification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Verification {
+            contract,
+            image_id,
+            status,
+        } = self;
+        match status {
+            VerificationStatus::Ok => write!(f, "ok           {contract} ({image_id})"),
+            VerificationStatus::Mismatch { program_id, policy } => write!(
+                f,
+                "mismatch     {contract}: on-chain {program_id}, expected {image_id} ({policy:?})"
+            ),
+            VerificationStatus::Unregistered { required: true } => {
+                write!(f, "missing      {contract} (expected {image_id})")
+            }
+            VerificationStatus::Unregistered { required: false } => {
+                write!(
+                    f,
+                    "unregistered {contract}, would be registered with {image_id}"
+                )
+            }
+        }
+    }
+}
 
-                for proof in proof_tx_builder.iter_prove() {
-                    let proof = proof.await.unwrap();
-                    node.send_tx_proof(&proof).await.unwrap();
+/// Runs the image id checks of [`init_node`] without submitting anything.
+pub async fn verify(
+    indexer: &IndexerApiHttpClient,
+    genesis: &Genesis,
+) -> Result<Vec<Verification>> {
+    let mut verifications = vec![];
+    for contract in genesis.registration_order()? {
+        let image_id = hex::encode(compute_image_id(&contract.elf()?)?);
+        let status = match indexer.get_indexer_contract(&contract.name).await {
+            Ok(onchain) => {
+                let program_id = hex::encode(onchain.program_id.as_slice());
+                if program_id == image_id {
+                    VerificationStatus::Ok
+                } else {
+                    VerificationStatus::Mismatch {
+                        program_id,
+                        policy: contract.on_mismatch,
+                    }
                 }
+            }
+            Err(_) => VerificationStatus::Unregistered {
+                required: !contract.register,
+            },
+        };
+        verifications.push(Verification {
+            contract: contract.name.clone(),
+            image_id,
+            status,
+        });
+    }
+    Ok(verifications)
+}
+
+/// How a manifest contract maps onto the chain, before looking at its state.
+enum Resolution {
+    Register,
+    Existing(StateDigest),
+    Skip,
+}
+
+/// Compares the on-chain contracts with the genesis manifest and lists what [`init_node`]
+/// would do, without submitting anything.
+pub async fn plan(
+    indexer: &IndexerApiHttpClient,
+    conf: &Conf,
+    genesis: &Genesis,
+    journal: &Journal,
+) -> Result<Plan> {
+    let mut actions = vec![];
+    let mut deployment = Deployment::default();
+    let mut resolutions = vec![];
 
-                timeout(Duration::from_secs(30), async {
-                    loop {
-                        if let Ok(contract) =node.get_contract(&"hyllar".into())
-                            .await
-                        {
-                            let contract = hyllar::HyllarTokenContract::init(
-                                contract.state.try_into().unwrap(),
-                                "faucet.hydentity".into(),
-                            );
-                            let balance = contract.balance_of("amm");
-                            if balance != Ok(1_000_000_000) {
-                                info!("⏰ Waiting for Hyllar contract state to be ready. amm balance is {balance:?}");
-                                debug!("state: {contract:#?}");
-                                tokio::time::sleep(Duration::from_millis(500)).await;
-                            } else {
-                                break;
-                            }
+    // Every registration is listed before the seeds, which only go out once all are done.
+    for contract in genesis.registration_order()? {
+        let name = &contract.name;
+        let image_id = hex::encode(compute_image_id(&contract.elf()?)?);
+
+        let resolution = match indexer.get_indexer_contract(name).await {
+            Err(_) if !contract.register => {
+                actions.push(Action::Mismatch {
+                    contract: name.clone(),
+                    reason: "contract is not registered".to_string(),
+                });
+                Resolution::Skip
+            }
+            Err(_) => Resolution::Register,
+            Ok(onchain) => {
+                let program_id = hex::encode(onchain.program_id.as_slice());
+                if program_id == image_id {
+                    Resolution::Existing(StateDigest(onchain.state_digest))
+                } else {
+                    match contract.on_mismatch {
+                        MismatchPolicy::Refuse => {
+                            actions.push(Action::Mismatch {
+                                contract: name.clone(),
+                                reason: mismatch_report(
+                                    contract,
+                                    &onchain.verifier,
+                                    &program_id,
+                                    &image_id,
+                                ),
+                            });
+                            Resolution::Skip
+                        }
+                        MismatchPolicy::ReadOnly => {
+                            deployment.insert(name, true);
+                            actions.push(Action::ReadOnly {
+                                contract: name.clone(),
+                                program_id,
+                                image_id,
+                            });
+                            Resolution::Skip
                         }
                     }
-                })
-                .await?;
+                }
+            }
+        };
+
+        match &resolution {
+            Resolution::Register => {
+                deployment.insert(name, false);
+                actions.push(match pending_registration(indexer, journal, name).await {
+                    Some(tx_hash) => Action::Registering {
+                        contract: name.clone(),
+                        tx_hash,
+                    },
+                    None => Action::Register {
+                        contract: name.clone(),
+                        image_id,
+                    },
+                });
             }
+            Resolution::Existing(_) => deployment.insert(name, false),
+            Resolution::Skip => {}
         }
-        Err(_) => {
-            bail!("Hyllar contract is not registered");
+        resolutions.push((contract, resolution));
+    }
+
+    for (contract, resolution) in resolutions {
+        let Resolution::Existing(state) = resolution else {
+            continue;
+        };
+        let seeds = match contract.kind {
+            ContractKind::Token => plan_token_seeds(conf, genesis, journal, contract, state)?,
+            ContractKind::Amm => plan_pools(genesis, contract, state)?,
+            ContractKind::Identity => vec![],
+        };
+        if seeds.is_empty() {
+            actions.push(Action::UpToDate {
+                contract: contract.name.clone(),
+            });
         }
+        actions.extend(seeds);
+    }
+
+    Ok(Plan {
+        actions,
+        deployment,
+    })
+}
+
+fn mismatch_report(
+    contract: &ContractManifest,
+    verifier: &str,
+    program_id: &str,
+    image_id: &str,
+) -> String {
+    format!(
+        "on-chain program id {program_id} ({verifier}) does not match image id {image_id} of ELF `{}`.\n  \
+         Set `on_mismatch = \"read_only\"` in the genesis manifest to keep serving against the on-chain program.",
+        contract.elf
+    )
+}
+
+fn seed_step(token: &ContractName) -> String {
+    format!("seed:{token}")
+}
+
+fn register_step(contract: &ContractName) -> String {
+    format!("register:{contract}")
+}
+
+/// Registration of `name` sent by a previous run that may still land, i.e. journaled without
+/// the indexer reporting it as failed.
+async fn pending_registration(
+    indexer: &IndexerApiHttpClient,
+    journal: &Journal,
+    name: &ContractName,
+) -> Option<TxHash> {
+    let step = journal.get(&register_step(name))?;
+    if step.status != StepStatus::Submitted {
+        return None;
+    }
+    let failed = indexer
+        .get_transaction_with_hash(&step.tx_hash)
+        .await
+        .is_ok_and(|tx| {
+            matches!(
+                tx.transaction_status,
+                TransactionStatus::Failure | TransactionStatus::TimedOut
+            )
+        });
+    (!failed).then(|| step.tx_hash.clone())
+}
+
+/// Seeds proved by a previous run are waited for. Otherwise seed transfers are sent to the
+/// recipients that don't hold any balance yet, along with the approvals that were never
+/// granted, so that seeds added to the manifest later on are sent as well.
+fn plan_token_seeds(
+    conf: &Conf,
+    genesis: &Genesis,
+    journal: &Journal,
+    contract: &ContractManifest,
+    state: StateDigest,
+) -> Result<Vec<Action>> {
+    let name = contract.name.clone();
+    if let Some(step) = journal
+        .get(&seed_step(&name))
+        .filter(|step| step.status == StepStatus::Proved)
+    {
+        return Ok(vec![Action::Pending {
+            token: name,
+            tx_hash: step.tx_hash.clone(),
+        }]);
+    }
+
+    let faucet = &conf.faucet.identity;
+    let token = HyllarTokenContract::init(state.try_into()?, faucet.clone());
+
+    let mut actions: Vec<_> = genesis
+        .transfers_for(&contract.name)
+        .map(|t| (t.recipient.clone(), t.amount))
+        .filter(|(recipient, _)| token.balance_of(recipient).is_err())
+        .map(|(recipient, amount)| Action::Fund {
+            token: name.clone(),
+            recipient,
+            amount,
+        })
+        .collect();
+    actions.extend(
+        genesis
+            .approvals_for(&contract.name)
+            .map(|a| (a.spender.clone(), a.amount))
+            .filter(|(spender, _)| token.allowance(&faucet.0, spender).unwrap_or(0) == 0)
+            .map(|(spender, amount)| Action::Approve {
+                token: name.clone(),
+                spender,
+                amount,
+            }),
+    );
+    Ok(actions)
+}
+
+/// Pools are only created when the AMM is registered, a missing one is reported.
+fn plan_pools(
+    genesis: &Genesis,
+    contract: &ContractManifest,
+    state: StateDigest,
+) -> Result<Vec<Action>> {
+    let amm: AmmState = state.try_into()?;
+    Ok(genesis
+        .pools
+        .iter()
+        .filter(|pool| pool.amm == contract.name)
+        .filter(|pool| {
+            amm.get_paired_amount(pool.tokens.0 .0.clone(), pool.tokens.1 .0.clone(), 0)
+                .is_none()
+        })
+        .map(|pool| Action::Mismatch {
+            contract: contract.name.clone(),
+            reason: format!("pool {}/{} is missing", pool.tokens.0, pool.tokens.1),
+        })
+        .collect())
+}
+
+async fn register_contract(
+    node: &NodeApiHttpClient,
+    indexer: &IndexerApiHttpClient,
+    conf: &Conf,
+    genesis: &Genesis,
+    journal: &mut Journal,
+    contract: &ContractManifest,
+    image_id: &str,
+) -> Result<()> {
+    let name = &contract.name;
+    info!("🚀 Registering {name} contract");
+    let tx_hash = node
+        .register_contract(&APIRegisterContract {
+            verifier: contract.verifier.as_str().into(),
+            program_id: ProgramId(hex::decode(image_id)?),
+            state_digest: genesis.initial_state(contract, &conf.faucet.identity)?,
+            contract_name: name.clone(),
+        })
+        .await?;
+    journal.record(&register_step(name), tx_hash.clone(), StepStatus::Submitted)?;
+    wait_contract_state(indexer, name, conf.bootstrap_timeout()).await?;
+    journal.record(&register_step(name), tx_hash, StepStatus::Settled)
+}
+
+/// Sends the planned seed transfers and approvals of a token in a single transaction from
+/// the faucet identity.
+async fn seed_token(
+    node: &Arc<NodeApiHttpClient>,
+    indexer: &IndexerApiHttpClient,
+    prover: &Arc<Prover>,
+    conf: &Conf,
+    deployment: &Deployment,
+    journal: &mut Journal,
+    token: &ContractName,
+    seeds: &[&Action],
+) -> Result<()> {
+    let faucet = &conf.faucet;
+
+    info!("🚀 Initializing {token} contract state");
+
+    let states = fetch_states(indexer).await?;
+    let settled_amm = states.amm.clone();
+    let mut app = HyleOofCtx {
+        executor: TxExecutorBuilder::new(states).build(),
+        client: node.clone(),
+        prover: prover.clone(),
+        identities: IdentityProviders::new(conf),
+        amm_cn: "amm".into(),
+        faucet: faucet.identity.clone(),
+        deployment: deployment.clone(),
+        synced_at: SystemTime::now(),
+        unsettled: vec![],
+        settled_amm,
     };
+    let mut transaction = ProvableBlobTx::new(faucet.identity.clone());
 
-    Ok(())
+    app.verify_identity(&mut transaction, faucet.password.clone())?;
+    for seed in seeds {
+        match seed {
+            Action::Fund {
+                recipient, amount, ..
+            } => app.transfer(&mut transaction, token.clone(), recipient.clone(), *amount)?,
+            Action::Approve {
+                spender, amount, ..
+            } => app.approve(&mut transaction, token.clone(), spender.clone(), *amount)?,
+            _ => {}
+        }
+    }
+
+    let blob_tx = BlobTransaction::new(transaction.identity.clone(), transaction.blobs.clone());
+    let tx_hash = blob_tx.hash();
+
+    let proof_tx_builder = app.executor.process(transaction)?;
+
+    // The states the seeds are built from only change once they settle, so seeds sent by a
+    // run that stopped before proving them hash the same: they are proved, not sent twice.
+    let step = seed_step(token);
+    let sent = journal
+        .get(&step)
+        .is_some_and(|s| s.status == StepStatus::Submitted && s.tx_hash == tx_hash)
+        && indexer.get_transaction_with_hash(&tx_hash).await.is_ok();
+    if sent {
+        info!("⏳ Resuming {token} initialization, {tx_hash} was already sent");
+    } else {
+        journal.record(&step, tx_hash.clone(), StepStatus::Submitted)?;
+        node.send_tx_blob(&blob_tx).await?;
+    }
+
+    info!("🚀 Proving blobs for {tx_hash}");
+
+    for proof in proof_tx_builder.iter_prove() {
+        let proof = proof.await?;
+        node.send_tx_proof(&proof).await?;
+    }
+    journal.record(&step, tx_hash.clone(), StepStatus::Proved)?;
+
+    settle_step(indexer, conf, journal, &step, &tx_hash).await
 }
 
-async fn init_hyllar2(node: &NodeApiHttpClient, indexer: &IndexerApiHttpClient) -> Result<()> {
-    match indexer.get_indexer_contract(&"hyllar2".into()).await {
-        Ok(contract) => {
-            let image_id = hex::encode(compute_image_id(HYLLAR_ELF)?);
-            let program_id = hex::encode(contract.program_id.as_slice());
-            if program_id != image_id {
-                bail!(
-                    "Invalid hyllar 2 contract image_id. On-chain version is {program_id}, expected {image_id}",
-                );
+/// Waits for a journaled transaction to settle and records the outcome. A failed step is
+/// sent again on the next run.
+async fn settle_step(
+    indexer: &IndexerApiHttpClient,
+    conf: &Conf,
+    journal: &mut Journal,
+    step: &str,
+    tx_hash: &TxHash,
+) -> Result<()> {
+    let status = timeout(conf.bootstrap_timeout(), async {
+        loop {
+            if let Ok(tx) = indexer.get_transaction_with_hash(tx_hash).await {
+                match tx.transaction_status {
+                    TransactionStatus::Success => return StepStatus::Settled,
+                    TransactionStatus::Failure | TransactionStatus::TimedOut => {
+                        return StepStatus::Failed
+                    }
+                    _ => {}
+                }
             }
-            info!("✅ Hyllar2 contract is up to date");
+            info!("⏰ Waiting for transaction {tx_hash} ({step}) to settle");
+            tokio::time::sleep(Duration::from_millis(500)).await;
         }
-        Err(_) => {
-            info!("🚀 Registering Hyllar2 contract");
-            let image_id = hex::encode(compute_image_id(HYLLAR_ELF)?);
-
-            let mut hyllar_token = hyllar::HyllarTokenContract::init(
-                hyllar::HyllarToken::new(100_000_000_000, "faucet.hydentity".to_string()),
-                "faucet.hydentity".into(),
-            );
-            hyllar_token.transfer("amm", 1_000_000_000).unwrap();
-
-            hyllar_token.approve("amm", 1_000_000_000_000_000).unwrap(); // faucet qui approve amm pour
-                                                                         // déplacer ses fonds
-            let hyllar_state = hyllar_token.state();
-
-            node.register_contract(&APIRegisterContract {
-                verifier: "risc0".into(),
-                program_id: ProgramId(hex::decode(image_id)?),
-                state_digest: hyllar_state.as_digest(),
-                contract_name: "hyllar2".into(),
-            })
-            .await?;
-            wait_contract_state(indexer, &"hyllar2".into()).await?;
-        }
-    };
+    })
+    .await
+    .map_err(|_| {
+        anyhow!("Transaction {tx_hash} ({step}) did not settle in time, restart to resume")
+    })?;
 
+    journal.record(step, tx_hash.clone(), status)?;
+    if status == StepStatus::Failed {
+        bail!("Transaction {tx_hash} ({step}) failed on-chain, restart to send it again");
+    }
     Ok(())
 }
 
 pub async fn wait_contract_state(
     indexer: &IndexerApiHttpClient,
     contract: &ContractName,
+    max_wait: Duration,
 ) -> anyhow::Result<()> {
-    timeout(Duration::from_secs(30), async {
+    timeout(max_wait, async {
         loop {
             let resp = indexer.get_indexer_contract(contract).await;
             if resp.is_err() {
@@ -214,3 +695,64 @@ pub async fn wait_contract_state(
     })
     .await?
 }
+
+#[cfg(test)]
+mod tests {
+    use hyllar::HyllarToken;
+    use sdk::Digestable;
+
+    use super::*;
+
+    fn seeds(journal: &Journal, state: StateDigest) -> Vec<Action> {
+        let conf = Conf::defaults();
+        let genesis = Genesis::load(None).unwrap();
+        let contract = genesis.contract(&"hyllar2".into()).unwrap();
+        plan_token_seeds(&conf, &genesis, journal, contract, state).unwrap()
+    }
+
+    fn unseeded() -> StateDigest {
+        let faucet = Conf::defaults().faucet.identity;
+        HyllarToken::new(100_000_000_000, faucet.0).as_digest()
+    }
+
+    #[test]
+    fn proved_seeds_are_waited_for_instead_of_sent_again() {
+        let path = std::env::temp_dir().join(format!("hyleoof-seeds-{}.json", std::process::id()));
+        let mut journal = Journal::open(path.clone()).unwrap();
+        journal
+            .record(
+                &seed_step(&"hyllar2".into()),
+                TxHash("0x1".to_string()),
+                StepStatus::Proved,
+            )
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let actions = seeds(&journal, unseeded());
+        assert!(
+            matches!(&actions[..], [Action::Pending { tx_hash, .. }] if tx_hash.0 == "0x1"),
+            "{actions:?}"
+        );
+    }
+
+    #[test]
+    fn seeds_are_planned_until_they_are_applied() {
+        let journal = Journal::default();
+        let actions = seeds(&journal, unseeded());
+        assert!(
+            matches!(
+                &actions[..],
+                [Action::Fund { recipient, .. }, Action::Approve { spender, .. }]
+                    if recipient == "amm" && spender == "amm"
+            ),
+            "{actions:?}"
+        );
+
+        let genesis = Genesis::load(None).unwrap();
+        let contract = genesis.contract(&"hyllar2".into()).unwrap();
+        let seeded = genesis
+            .initial_state(contract, &Conf::defaults().faucet.identity)
+            .unwrap();
+        assert!(seeds(&journal, seeded).is_empty());
+    }
+}
@@ -0,0 +1,93 @@
+use std::{collections::BTreeMap, path::PathBuf, time::SystemTime};
+
+use anyhow::Result;
+use sdk::TxHash;
+use serde::{Deserialize, Serialize};
+
+use crate::store::{load_json, save_json};
+
+/// Bootstrap steps submitted by `init_node`, persisted so that a restart resumes or verifies
+/// them instead of sending them again.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Journal {
+    #[serde(skip)]
+    path: PathBuf,
+    steps: BTreeMap<String, Step>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Step {
+    pub tx_hash: TxHash,
+    pub status: StepStatus,
+    /// Seconds since the unix epoch.
+    pub updated_at: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepStatus {
+    /// The blob transaction is about to be sent, or was sent without its proofs.
+    Submitted,
+    /// All proofs were sent to the node.
+    Proved,
+    /// The indexer reports the transaction as successful.
+    Settled,
+    /// The transaction failed or timed out on-chain, the step can be sent again.
+    Failed,
+}
+
+impl Journal {
+    pub fn open(path: PathBuf) -> Result<Self> {
+        let mut journal: Journal = load_json(&path)?;
+        journal.path = path;
+        Ok(journal)
+    }
+
+    pub fn get(&self, step: &str) -> Option<&Step> {
+        self.steps.get(step)
+    }
+
+    pub fn record(&mut self, step: &str, tx_hash: TxHash, status: StepStatus) -> Result<()> {
+        let updated_at = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_secs();
+        self.steps.insert(
+            step.to_string(),
+            Step {
+                tx_hash,
+                status,
+                updated_at,
+            },
+        );
+        save_json(&self.path, self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_survive_a_restart() {
+        let path =
+            std::env::temp_dir().join(format!("hyleoof-journal-{}.json", std::process::id()));
+        let mut journal = Journal::open(path.clone()).unwrap();
+        journal
+            .record(
+                "seed:hyllar",
+                TxHash("0x1".to_string()),
+                StepStatus::Submitted,
+            )
+            .unwrap();
+        journal
+            .record("seed:hyllar", TxHash("0x1".to_string()), StepStatus::Proved)
+            .unwrap();
+
+        let journal = Journal::open(path.clone()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let step = journal.get("seed:hyllar").unwrap();
+        assert_eq!(step.tx_hash, TxHash("0x1".to_string()));
+        assert_eq!(step.status, StepStatus::Proved);
+        assert!(journal.get("seed:hyllar2").is_none());
+    }
+}
@@ -1,191 +1,687 @@
-use std::{env, sync::Arc};
+use std::{
+    env,
+    sync::{Arc, Mutex as SyncMutex},
+    time::{Duration, Instant, SystemTime},
+};
 
 use amm::AmmState;
-use anyhow::Result;
+use anyhow::{anyhow, bail, Context, Result};
 use axum::{
-    extract::{Json, State},
-    http::Method,
-    response::IntoResponse,
+    extract::{Json, MatchedPath, Path, Query, Request, State},
+    http::{header, HeaderValue, Method, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
     routing::{get, post},
     Router,
 };
+use clap::{Parser, Subcommand};
 use client_sdk::{
     contract_states,
     rest_client::{IndexerApiHttpClient, NodeApiHttpClient},
     transaction_builder::{ProvableBlobTx, TxExecutor, TxExecutorBuilder},
 };
+use conf::{Conf, LogFormat};
+use history::History;
 use hydentity::Hydentity;
-use hyllar::HyllarToken;
-use reqwest::{Client, Url};
-use sdk::BlobTransaction;
+use hyleoof_api::{
+    ApproveRequest, BatchRequest, CandleQuery, CandlesResponse, Check, FaucetRequest, HealthReport,
+    HistoryEntry, HistoryQuery, HistoryResponse, IdentityResponse, Operation, PoolResponse,
+    QuoteQuery, QuoteResponse, RegisterRequest, SimulateRequest, SimulateResponse, SwapRequest,
+    TransferFromRequest, TransferRequest, TwapQuery, TwapResponse, TxHashResponse,
+    TxStatusResponse, ValidationErrors,
+};
+use hyllar::{HyllarToken, HyllarTokenContract};
+use idempotency::{IdempotencyKey, IdempotencyStore, Submission};
+use identity::IdentityProviders;
+use init::Deployment;
+use preflight::Preflight;
+use prices::PriceStore;
+use reqwest::Client;
+use sdk::{erc20::ERC20, BlobTransaction, Hashed};
 use sdk::{ContractName, Identity, TxHash};
-use serde::Deserialize;
 use task_manager::Prover;
-use tokio::sync::Mutex;
-use tower_http::cors::{Any, CorsLayer};
-use tracing::{error, info, level_filters::LevelFilter};
+use tokio::sync::{Mutex, OwnedMutexGuard};
+use tower_http::{
+    cors::{AllowOrigin, Any, CorsLayer},
+    timeout::TimeoutLayer,
+    trace::{DefaultMakeSpan, DefaultOnResponse, TraceLayer},
+};
+use tracing::{
+    error, field::Empty, info, instrument, level_filters::LevelFilter, Instrument, Level, Span,
+};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
+use twap::Oracle;
 use utils::AppError;
+use utoipa::OpenApi;
+use validation::Validator;
 
+mod conf;
+mod dump;
+mod genesis;
+mod health;
+mod history;
+mod idempotency;
+mod identity;
 mod init;
+mod journal;
+mod metrics;
+#[cfg(feature = "otlp")]
+mod otlp;
+mod pools;
+mod preflight;
+mod prices;
+mod resync;
+mod simulate;
+mod store;
 mod task_manager;
+mod twap;
 mod utils;
+mod validation;
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Configuration file, defaults to `hyleoof.toml` when present
+    #[arg(long, global = true)]
+    config_file: Option<String>,
+
+    #[arg(long, global = true)]
+    node_url: Option<String>,
+
+    #[arg(long, global = true)]
+    indexer_url: Option<String>,
+
+    /// Address the HTTP server binds to
+    #[arg(long, global = true)]
+    host: Option<String>,
+
+    #[arg(long, global = true)]
+    data_dir: Option<String>,
+
+    /// Genesis manifest describing the contracts to bootstrap
+    #[arg(long, global = true)]
+    genesis: Option<String>,
+}
+
+#[derive(Subcommand, Debug, Default)]
+enum Command {
+    /// Bootstrap the contracts and serve the HTTP API (default)
+    #[default]
+    Serve,
+    /// Bootstrap the contracts of the genesis manifest and exit
+    Init {
+        /// Print the bootstrap actions without submitting anything
+        #[arg(long, default_value_t = false)]
+        plan: bool,
+    },
+    /// Check on-chain program ids against the bundled ELFs without submitting anything
+    Verify,
+    /// Print the contract states fetched from the indexer as JSON
+    DumpState,
+    /// Print the OpenAPI document of the HTTP API
+    Openapi,
+}
 
 #[derive(Clone)]
 struct RouterCtx {
     pub app: Arc<Mutex<HyleOofCtx>>,
+    pub conf: Arc<Conf>,
+    pub indexer: Arc<IndexerApiHttpClient>,
+    pub idempotency: Arc<SyncMutex<IdempotencyStore>>,
+    pub history: Arc<SyncMutex<History>>,
+    pub prices: Arc<SyncMutex<PriceStore>>,
+    pub oracle: Arc<SyncMutex<Oracle>>,
 }
 
-async fn build_app_context(
-    indexer: Arc<IndexerApiHttpClient>,
-    node: Arc<NodeApiHttpClient>,
-) -> HyleOofCtx {
-    let hyllar = indexer.fetch_current_state(&"hyllar".into()).await.unwrap();
+impl RouterCtx {
+    async fn lock_app(&self) -> OwnedMutexGuard<HyleOofCtx> {
+        let started = Instant::now();
+        let app = self.app.clone().lock_owned().await;
+        metrics::APP_LOCK_WAIT.observe(started.elapsed().as_secs_f64());
+        app
+    }
+}
+
+async fn fetch_states(indexer: &IndexerApiHttpClient) -> Result<States> {
+    let hyllar = indexer
+        .fetch_current_state(&"hyllar".into())
+        .await
+        .context("fetching hyllar state")?;
     let hyllar2 = indexer
         .fetch_current_state(&"hyllar2".into())
         .await
-        .unwrap();
+        .context("fetching hyllar2 state")?;
     let hydentity = indexer
         .fetch_current_state(&"hydentity".into())
         .await
-        .unwrap();
-    let amm = indexer.fetch_current_state(&"amm".into()).await.unwrap();
+        .context("fetching hydentity state")?;
+    let amm = indexer
+        .fetch_current_state(&"amm".into())
+        .await
+        .context("fetching amm state")?;
 
-    let executor = TxExecutorBuilder::new(States {
+    Ok(States {
         hyllar,
         hyllar2,
         hydentity,
         amm,
     })
-    .build();
+}
+
+async fn build_app_context(
+    indexer: Arc<IndexerApiHttpClient>,
+    node: Arc<NodeApiHttpClient>,
+    prover: Arc<Prover>,
+    conf: &Conf,
+    deployment: Deployment,
+) -> Result<HyleOofCtx> {
+    let states = fetch_states(&indexer).await?;
+    let settled_amm = states.amm.clone();
+    let executor = TxExecutorBuilder::new(states).build();
 
-    HyleOofCtx {
+    let app = HyleOofCtx {
         executor,
-        client: node.clone(),
-        prover: Arc::new(Prover::new(node)),
-        hydentity_cn: "hydentity".into(),
+        client: node,
+        prover,
+        identities: IdentityProviders::new(conf),
         amm_cn: "amm".into(),
-    }
+        faucet: conf.faucet.identity.clone(),
+        deployment,
+        synced_at: SystemTime::now(),
+        unsettled: Default::default(),
+        settled_amm,
+    };
+    app.record_state_metrics();
+    Ok(app)
 }
 
-fn setup_tracing() {
+fn setup_tracing(conf: &Conf) -> Result<()> {
     let mut filter = EnvFilter::builder()
         .with_default_directive(LevelFilter::INFO.into())
-        .from_env()
-        .unwrap();
+        .from_env()?;
     let var = std::env::var("RUST_LOG").unwrap_or("".to_string());
     if !var.contains("risc0_zkvm") {
-        filter = filter.add_directive("risc0_zkvm=info".parse().unwrap());
-        filter = filter.add_directive("risc0_circuit_rv32im=info".parse().unwrap());
+        filter = filter.add_directive("risc0_zkvm=info".parse()?);
+        filter = filter.add_directive("risc0_circuit_rv32im=info".parse()?);
+    }
+
+    let fmt_layer = match conf.log.format {
+        LogFormat::Text => tracing_subscriber::fmt::layer().boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .boxed(),
+    };
+    let registry = tracing_subscriber::registry().with(fmt_layer.with_filter(filter));
+
+    #[cfg(feature = "otlp")]
+    let registry = {
+        let tracer = conf
+            .log
+            .otlp_endpoint
+            .as_deref()
+            .map(otlp::tracer)
+            .transpose()?;
+        registry.with(tracer.map(|tracer| {
+            tracing_opentelemetry::layer()
+                .with_tracer(tracer)
+                .with_filter(LevelFilter::INFO)
+        }))
+    };
+    #[cfg(not(feature = "otlp"))]
+    if conf.log.otlp_endpoint.is_some() {
+        bail!("log.otlp_endpoint requires building the server with the `otlp` feature");
     }
 
-    tracing_subscriber::registry()
-        .with(tracing_subscriber::fmt::layer().with_filter(filter))
-        .init();
+    registry.init();
+    Ok(())
 }
 
-#[tokio::main]
-async fn main() {
-    setup_tracing();
+fn cors_layer(conf: &Conf) -> Result<CorsLayer> {
+    let origins = &conf.cors.allowed_origins;
+    let allow_origin = if origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(
+            origins
+                .iter()
+                .map(|origin| HeaderValue::from_str(origin))
+                .collect::<Result<Vec<_>, _>>()?,
+        )
+    };
+    Ok(CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods(vec![Method::GET, Method::POST])
+        .allow_headers(Any))
+}
 
-    let node_url = env::var("NODE_URL").unwrap_or_else(|_| "http://localhost:4321".to_string());
-    let indexer_url =
-        env::var("INDEXER_URL").unwrap_or_else(|_| "http://localhost:4321".to_string());
+fn main() {
+    let args = Args::parse();
+    let command = args.command.unwrap_or_default();
+    if let Command::Openapi = command {
+        match ApiDoc::openapi().to_pretty_json() {
+            Ok(document) => println!("{document}"),
+            Err(e) => {
+                eprintln!("Error serializing the OpenAPI document: {e:?}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    let conf = Conf::new(
+        args.config_file.as_deref(),
+        conf::Overrides {
+            node_url: args.node_url,
+            indexer_url: args.indexer_url,
+            host: args.host,
+            data_dir: args.data_dir,
+            genesis: args.genesis,
+        },
+    );
+    // Tracing is configured from the configuration, errors before that go to stderr.
+    let conf = match conf {
+        Ok(conf) => conf,
+        Err(e) => {
+            eprintln!("Error loading configuration: {e:?}");
+            std::process::exit(1);
+        }
+    };
+    // Set before the runtime spawns any thread, changing the environment is not thread safe.
+    if let Some(backend) = &conf.prover.backend {
+        env::set_var("RISC0_PROVER", backend);
+    }
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("Error starting the async runtime: {e:?}");
+            std::process::exit(1);
+        }
+    };
+    runtime.block_on(start(command, conf));
+}
+
+async fn start(command: Command, conf: Conf) {
+    if let Err(e) = setup_tracing(&conf) {
+        eprintln!("Error setting up tracing: {e:?}");
+        std::process::exit(1);
+    }
+
+    let result = run(command, conf).await;
+
+    #[cfg(feature = "otlp")]
+    opentelemetry::global::shutdown_tracer_provider();
+
+    if let Err(e) = result {
+        error!("{:?}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn run(command: Command, conf: Conf) -> Result<()> {
+    let reqwest_client = Client::builder()
+        .timeout(Duration::from_secs(conf.timeouts.node_client_secs))
+        .build()?;
     let node_client = Arc::new(NodeApiHttpClient {
-        url: Url::parse(node_url.as_str()).unwrap(),
-        reqwest_client: Client::new(),
+        url: conf.node_url()?,
+        reqwest_client: reqwest_client.clone(),
     });
     let indexer_client = Arc::new(IndexerApiHttpClient {
-        url: Url::parse(indexer_url.as_str()).unwrap(),
-        reqwest_client: Client::new(),
+        url: conf.indexer_url()?,
+        reqwest_client,
     });
 
-    match init::init_node(node_client.clone(), indexer_client.clone()).await {
-        Ok(_) => {}
-        Err(e) => {
-            error!("Error initializing node: {:?}", e);
-            return;
+    let genesis = genesis::Genesis::load(conf.genesis.as_deref())
+        .context("Error loading genesis manifest")?;
+
+    let mut journal = journal::Journal::open(conf.data_dir.join("bootstrap.json"))
+        .context("Error opening bootstrap journal")?;
+
+    match command {
+        Command::Serve => serve(conf, node_client, indexer_client, &genesis, &mut journal).await,
+        Command::Init { plan: true } => {
+            let plan = init::plan(&indexer_client, &conf, &genesis, &journal)
+                .await
+                .context("Error planning node initialization")?;
+            for action in plan.actions {
+                println!("{action}");
+            }
+            Ok(())
+        }
+        Command::Init { plan: false } => {
+            let prover = Arc::new(Prover::new(node_client.clone()));
+            init::init_node(
+                node_client,
+                indexer_client,
+                &prover,
+                &conf,
+                &genesis,
+                &mut journal,
+            )
+            .await
+            .context("Error initializing node")?;
+            info!("✅ Node initialized");
+            Ok(())
+        }
+        Command::Verify => {
+            let verifications = init::verify(&indexer_client, &genesis)
+                .await
+                .context("Error verifying contracts")?;
+            for verification in &verifications {
+                println!("{verification}");
+            }
+            if verifications.iter().any(|v| v.is_fatal()) {
+                bail!("Some contracts do not match the genesis manifest");
+            }
+            Ok(())
         }
+        Command::DumpState => {
+            let states = fetch_states(&indexer_client).await?;
+            let dump = dump::dump(&states).context("Error dumping contract states")?;
+            println!("{}", serde_json::to_string_pretty(&dump)?);
+            Ok(())
+        }
+        Command::Openapi => unreachable!("printed before loading the configuration"),
     }
+}
+
+async fn serve(
+    conf: Conf,
+    node_client: Arc<NodeApiHttpClient>,
+    indexer_client: Arc<IndexerApiHttpClient>,
+    genesis: &genesis::Genesis,
+    journal: &mut journal::Journal,
+) -> Result<()> {
+    // Shared by the seeds sent during initialization and the transactions served afterwards.
+    let prover = Arc::new(Prover::new(node_client.clone()));
+    let deployment = init::init_node(
+        node_client.clone(),
+        indexer_client.clone(),
+        &prover,
+        &conf,
+        genesis,
+        journal,
+    )
+    .await
+    .context("Error initializing node")?;
+    let app = build_app_context(
+        indexer_client.clone(),
+        node_client,
+        prover.clone(),
+        &conf,
+        deployment,
+    )
+    .await
+    .context("Error building app context")?;
+    let cors = cors_layer(&conf)?;
+    let request_timeout = Duration::from_secs(conf.timeouts.request_secs);
+    let addr = conf.host.clone();
+    let shutdown_timeout = Duration::from_secs(conf.timeouts.shutdown_secs);
+
+    let idempotency = IdempotencyStore::open(
+        conf.data_dir.join("idempotency.json"),
+        Duration::from_secs(conf.idempotency.ttl_secs),
+    )
+    .context("Error opening idempotency keys")?;
+    let history = History::open(&conf.data_dir.join("history.jsonl"))
+        .context("Error opening transaction history")?;
+    let prices = PriceStore::open(
+        conf.data_dir.join("prices.json"),
+        Duration::from_secs(conf.prices.retention_secs),
+    )
+    .context("Error opening price history")?;
+    let oracle = Oracle::open(conf.data_dir.join("twap.json"), conf.twap.max_window_secs)
+        .context("Error opening TWAP accumulators")?;
 
     let state = RouterCtx {
-        app: Arc::new(Mutex::new(
-            build_app_context(indexer_client, node_client).await,
-        )),
+        app: Arc::new(Mutex::new(app)),
+        conf: Arc::new(conf),
+        indexer: indexer_client,
+        idempotency: Arc::new(SyncMutex::new(idempotency)),
+        history: Arc::new(SyncMutex::new(history)),
+        prices: Arc::new(SyncMutex::new(prices)),
+        oracle: Arc::new(SyncMutex::new(oracle)),
     };
+    tokio::spawn(resync::sync_states(state.clone()));
+    if state.conf.prices.sync_interval_secs > 0 {
+        tokio::spawn(prices::sync(state.clone()));
+    }
+    if state.conf.twap.sync_interval_secs > 0 {
+        tokio::spawn(twap::sync(state.clone()));
+    }
 
-    // Créer un middleware CORS
-    let cors = CorsLayer::new()
-        .allow_origin(Any) // Permet toutes les origines (peut être restreint)
-        .allow_methods(vec![Method::GET, Method::POST]) // Permet les méthodes nécessaires
-        .allow_headers(Any); // Permet tous les en-têtes
-
-    let app = Router::new()
-        .route("/_health", get(health))
+    // Endpoints submitting a transaction, retried safely with an `Idempotency-Key`.
+    let submitting = Router::new()
         .route("/api/faucet", post(faucet))
         .route("/api/transfer", post(transfer))
+        .route("/api/transfer-from", post(transfer_from))
         .route("/api/register", post(register))
         .route("/api/approve", post(approve))
         .route("/api/swap", post(swap))
+        .route("/api/batch", post(batch))
+        .route_layer(middleware::from_fn_with_state(
+            state.idempotency.clone(),
+            idempotency::layer,
+        ));
+
+    let app = Router::new()
+        .route("/_health", get(health))
+        .route("/ready", get(ready))
+        .route("/metrics", get(metrics_handler))
+        .route("/api/openapi.json", get(openapi))
+        .route("/api/quote", get(quote))
+        .route("/api/pools", get(list_pools))
+        .route("/api/pools/{token_a}/{token_b}", get(pool))
+        .route("/api/pools/{token_a}/{token_b}/candles", get(candles))
+        .route("/api/pools/{token_a}/{token_b}/twap", get(twap))
+        .route("/api/simulate", post(simulate))
+        .route("/api/tx/{tx_hash}", get(tx_status))
+        .route("/api/accounts/{identity}/history", get(account_history))
+        .route("/api/identity/{name}", get(identity))
+        .merge(submitting)
         .with_state(state)
-        .layer(cors); // Appliquer le middleware CORS
+        .layer(middleware::from_fn(track_requests))
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(DefaultMakeSpan::new().level(Level::INFO))
+                .on_response(DefaultOnResponse::new().level(Level::INFO)),
+        )
+        .layer(TimeoutLayer::new(request_timeout))
+        .layer(cors);
 
-    let addr: String = env::var("HYLEOOF_HOST")
-        .unwrap_or_else(|_| "127.0.0.1:3000".to_string())
-        .parse()
-        .unwrap();
+    let listener = tokio::net::TcpListener::bind(&addr)
+        .await
+        .with_context(|| format!("Error binding {addr}"))?;
     info!("Server running on {}", addr);
-    _ = axum::serve(tokio::net::TcpListener::bind(&addr).await.unwrap(), app).await;
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
+        .await?;
+
+    info!("Server stopped, draining the proving queue");
+    let unproven = prover.shutdown(shutdown_timeout).await;
+    if unproven.is_empty() {
+        info!("All transactions proven");
+        return Ok(());
+    }
+    // Their proofs cannot be rebuilt after a restart, they time out on-chain.
+    for tx_hash in &unproven {
+        error!("Transaction {tx_hash} was submitted but not proven");
+    }
+    Ok(())
+}
+
+/// Resolves on Ctrl+C or SIGTERM.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for Ctrl+C: {e}");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {e}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    info!("Shutdown signal received, no longer accepting requests");
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "hyleoof",
+        description = "Hyle token faucet, transfers and AMM swaps"
+    ),
+    paths(
+        faucet,
+        transfer,
+        transfer_from,
+        approve,
+        swap,
+        batch,
+        register,
+        identity,
+        quote,
+        list_pools,
+        pool,
+        candles,
+        twap,
+        simulate,
+        tx_status,
+        account_history,
+        ready
+    ),
+    components(schemas(Check, HealthReport, ValidationErrors))
+)]
+struct ApiDoc;
+
+async fn openapi() -> impl IntoResponse {
+    Json(ApiDoc::openapi())
 }
 
 async fn health() -> impl IntoResponse {
     Json("OK")
 }
 
+#[utoipa::path(
+    get,
+    path = "/ready",
+    responses(
+        (status = 200, description = "Every check passed", body = HealthReport),
+        (status = 503, description = "At least one check failed", body = HealthReport),
+    )
+)]
+async fn ready(State(ctx): State<RouterCtx>) -> impl IntoResponse {
+    let report = health::report(&ctx).await;
+    let status = if report.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(report))
+}
+
+async fn metrics_handler() -> Result<impl IntoResponse, AppError> {
+    Ok((
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics::render()?,
+    ))
+}
+
+async fn track_requests(request: Request, next: Next) -> Response {
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let started = Instant::now();
+
+    let response = next.run(request).await;
+
+    metrics::HTTP_REQUEST_DURATION
+        .with_label_values(&[&method, &route])
+        .observe(started.elapsed().as_secs_f64());
+    metrics::HTTP_REQUESTS
+        .with_label_values(&[&method, &route, response.status().as_str()])
+        .inc();
+    response
+}
+
 // --------------------------------------------------------
 //      Faucet
 // --------------------------------------------------------
 
-#[derive(Deserialize)]
-struct FaucetRequest {
-    username: String,
-    token: ContractName,
-}
-
+#[utoipa::path(
+    post,
+    path = "/api/faucet",
+    params(IdempotencyKey),
+    request_body = FaucetRequest,
+    responses(
+        (status = 200, description = "Faucet transfer submitted", body = TxHashResponse),
+        (status = 400, description = "Invalid request", body = ValidationErrors),
+        (status = 422, description = "Transaction would fail on-chain", body = ValidationErrors),
+        (status = 500, description = "Transaction rejected or not submitted", body = String),
+    )
+)]
 async fn faucet(
     State(ctx): State<RouterCtx>,
     Json(payload): Json<FaucetRequest>,
 ) -> Result<impl IntoResponse, AppError> {
+    ctx.validate(&payload).await?;
+    let faucet = ctx.conf.faucet.clone();
     let tx_hash = do_transfer(
         ctx,
-        "faucet.hydentity".into(),
-        "password".into(),
+        faucet.identity,
+        faucet.password,
         payload.username,
         payload.token,
-        10,
+        faucet.amount,
     )
     .await?;
 
-    Ok(Json(tx_hash))
+    Ok(Json(TxHashResponse { tx_hash }))
 }
 
 // --------------------------------------------------------
 //      Transfer
 // --------------------------------------------------------
 
-#[derive(Deserialize)]
-struct TransferRequest {
-    username: String,
-    password: String,
-    recipient: String,
-    token: ContractName,
-    amount: u128,
-}
-
+#[utoipa::path(
+    post,
+    path = "/api/transfer",
+    params(IdempotencyKey),
+    request_body = TransferRequest,
+    responses(
+        (status = 200, description = "Transfer submitted", body = TxHashResponse),
+        (status = 400, description = "Invalid request", body = ValidationErrors),
+        (status = 422, description = "Transaction would fail on-chain", body = ValidationErrors),
+        (status = 500, description = "Transaction rejected or not submitted", body = String),
+    )
+)]
 async fn transfer(
     State(ctx): State<RouterCtx>,
     Json(payload): Json<TransferRequest>,
 ) -> Result<impl IntoResponse, AppError> {
+    ctx.validate(&payload).await?;
     let tx_hash = do_transfer(
         ctx,
         payload.username.into(),
@@ -195,26 +691,63 @@ async fn transfer(
         payload.amount,
     )
     .await?;
-    Ok(Json(tx_hash))
+    Ok(Json(TxHashResponse { tx_hash }))
 }
 
 // --------------------------------------------------------
-//    Approve
+//    Transfer from
 // --------------------------------------------------------
 
-#[derive(Deserialize)]
-struct ApproveRequest {
-    username: String,
-    password: String,
-    spender: String,
-    token: String,
-    amount: u128,
+#[utoipa::path(
+    post,
+    path = "/api/transfer-from",
+    params(IdempotencyKey),
+    request_body = TransferFromRequest,
+    responses(
+        (status = 200, description = "Transfer submitted", body = TxHashResponse),
+        (status = 400, description = "Invalid request", body = ValidationErrors),
+        (status = 422, description = "Transaction would fail on-chain", body = ValidationErrors),
+        (status = 500, description = "Transaction rejected or not submitted", body = String),
+    )
+)]
+async fn transfer_from(
+    State(ctx): State<RouterCtx>,
+    Json(payload): Json<TransferFromRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    ctx.validate(&payload).await?;
+    let tx_hash = do_transfer_from(
+        ctx,
+        payload.username.into(),
+        payload.password,
+        payload.owner,
+        payload.recipient,
+        payload.token,
+        payload.amount,
+    )
+    .await?;
+    Ok(Json(TxHashResponse { tx_hash }))
 }
 
+// --------------------------------------------------------
+//    Approve
+// --------------------------------------------------------
+
+#[utoipa::path(
+    post,
+    path = "/api/approve",
+    params(IdempotencyKey),
+    request_body = ApproveRequest,
+    responses(
+        (status = 200, description = "Approval submitted", body = TxHashResponse),
+        (status = 400, description = "Invalid request", body = ValidationErrors),
+        (status = 500, description = "Transaction rejected or not submitted", body = String),
+    )
+)]
 async fn approve(
     State(ctx): State<RouterCtx>,
     Json(payload): Json<ApproveRequest>,
 ) -> Result<impl IntoResponse, AppError> {
+    ctx.validate(&payload).await?;
     let tx_hash = do_approve(
         ctx,
         payload.username.into(),
@@ -224,26 +757,30 @@ async fn approve(
         payload.amount,
     )
     .await?;
-    Ok(Json(tx_hash))
+    Ok(Json(TxHashResponse { tx_hash }))
 }
 
 // --------------------------------------------------------
 //   Swap
 // --------------------------------------------------------
 
-#[derive(Deserialize)]
-struct SwapRequest {
-    username: Identity,
-    password: String,
-    token_a: ContractName,
-    token_b: ContractName,
-    amount: u128,
-}
-
+#[utoipa::path(
+    post,
+    path = "/api/swap",
+    params(IdempotencyKey),
+    request_body = SwapRequest,
+    responses(
+        (status = 200, description = "Swap submitted", body = TxHashResponse),
+        (status = 400, description = "Invalid request", body = ValidationErrors),
+        (status = 422, description = "Transaction would fail on-chain", body = ValidationErrors),
+        (status = 500, description = "Transaction rejected or not submitted", body = String),
+    )
+)]
 async fn swap(
     State(ctx): State<RouterCtx>,
     Json(payload): Json<SwapRequest>,
 ) -> Result<impl IntoResponse, AppError> {
+    ctx.validate(&payload).await?;
     let SwapRequest {
         username,
         password,
@@ -253,45 +790,391 @@ async fn swap(
     } = payload;
 
     let tx_hash = do_swap(ctx, username, password, token_a, token_b, amount).await?;
-    Ok(Json(tx_hash))
+    Ok(Json(TxHashResponse { tx_hash }))
 }
 
 // --------------------------------------------------------
-//      Register
+//   Quote
 // --------------------------------------------------------
 
-#[derive(Deserialize)]
-struct RegisterRequest {
-    username: Identity,
-    password: String,
+#[utoipa::path(
+    get,
+    path = "/api/quote",
+    params(QuoteQuery),
+    responses(
+        (status = 200, body = QuoteResponse),
+        (status = 400, description = "Invalid request", body = ValidationErrors),
+        (status = 500, description = "Unknown token pair", body = String),
+    )
+)]
+async fn quote(
+    State(ctx): State<RouterCtx>,
+    Query(query): Query<QuoteQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    ctx.validate(&query).await?;
+    let app = ctx.lock_app().await;
+    let amount_b = app.quote(query.token_a.clone(), query.token_b.clone(), query.amount)?;
+    Ok(Json(QuoteResponse {
+        token_a: query.token_a,
+        token_b: query.token_b,
+        amount_a: query.amount,
+        amount_b,
+    }))
+}
+
+// --------------------------------------------------------
+//   Pools
+// --------------------------------------------------------
+
+/// Window of the volume reported by the pool endpoints.
+const POOL_VOLUME_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+fn pool_volume_since() -> u64 {
+    SystemTime::now()
+        .checked_sub(POOL_VOLUME_WINDOW)
+        .and_then(|since| since.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_secs())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/pools",
+    responses((status = 200, body = Vec<PoolResponse>))
+)]
+async fn list_pools(State(ctx): State<RouterCtx>) -> Result<impl IntoResponse, AppError> {
+    let app = ctx.lock_app().await;
+    let pools = app.pools()?;
+    let reference = &ctx.conf.pools.reference_token.0;
+    let since = pool_volume_since();
+
+    let history = ctx.history.lock().unwrap();
+    let response: Vec<PoolResponse> = pools
+        .iter()
+        .map(|pool| pools::analytics(&pools, pool.clone(), reference, &history, since))
+        .collect();
+    Ok(Json(response))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/pools/{token_a}/{token_b}",
+    params(
+        ("token_a" = String, Path, description = "Token the prices are given for"),
+        ("token_b" = String, Path, description = "Token the prices are given in"),
+    ),
+    responses(
+        (status = 200, body = PoolResponse),
+        (status = 404, description = "No pool pairs the tokens", body = String),
+    )
+)]
+async fn pool(
+    State(ctx): State<RouterCtx>,
+    Path((token_a, token_b)): Path<(String, String)>,
+) -> Result<impl IntoResponse, AppError> {
+    let app = ctx.lock_app().await;
+    let pools = app.pools()?;
+    let pool = pools::find(&pools, &token_a, &token_b).ok_or_else(|| {
+        AppError(
+            StatusCode::NOT_FOUND,
+            anyhow!("no pool pairs {token_a} and {token_b}"),
+        )
+    })?;
+
+    let history = ctx.history.lock().unwrap();
+    Ok(Json(pools::analytics(
+        &pools,
+        pool,
+        &ctx.conf.pools.reference_token.0,
+        &history,
+        pool_volume_since(),
+    )))
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/pools/{token_a}/{token_b}/candles",
+    params(
+        ("token_a" = String, Path, description = "Token the prices are given for"),
+        ("token_b" = String, Path, description = "Token the prices are given in"),
+        CandleQuery,
+    ),
+    responses(
+        (status = 200, body = CandlesResponse),
+        (status = 400, description = "Invalid request", body = ValidationErrors),
+    )
+)]
+async fn candles(
+    State(ctx): State<RouterCtx>,
+    Path((token_a, token_b)): Path<(String, String)>,
+    Query(query): Query<CandleQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    {
+        let app = ctx.lock_app().await;
+        let mut validator = Validator::new(&app);
+        let intervals = &ctx.conf.prices.intervals_secs;
+        if !intervals.contains(&query.interval) {
+            validator.error("interval", format!("must be one of {intervals:?}"));
+        }
+        validator.token("token_a", &token_a.clone().into());
+        validator.token("token_b", &token_b.clone().into());
+        validator.finish()?;
+    }
+
+    let to = query.to.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |now| now.as_secs())
+    });
+    let from = query
+        .from
+        .unwrap_or_else(|| to.saturating_sub(100 * query.interval));
+    let candles = ctx
+        .prices
+        .lock()
+        .unwrap()
+        .candles(&token_a, &token_b, query.interval, from, to);
+    Ok(Json(CandlesResponse {
+        token_a,
+        token_b,
+        interval: query.interval,
+        candles,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/pools/{token_a}/{token_b}/twap",
+    params(
+        ("token_a" = String, Path, description = "Token the prices are given for"),
+        ("token_b" = String, Path, description = "Token the prices are given in"),
+        TwapQuery,
+    ),
+    responses(
+        (status = 200, body = TwapResponse),
+        (status = 400, description = "Invalid request", body = ValidationErrors),
+        (status = 404, description = "Not enough blocks observed for the pair", body = String),
+    )
+)]
+async fn twap(
+    State(ctx): State<RouterCtx>,
+    Path((token_a, token_b)): Path<(String, String)>,
+    Query(query): Query<TwapQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    {
+        let app = ctx.lock_app().await;
+        let mut validator = Validator::new(&app);
+        let max_window = ctx.conf.twap.max_window_secs;
+        if query.window == 0 || query.window > max_window {
+            validator.error("window", format!("must be between 1 and {max_window}"));
+        }
+        validator.token("token_a", &token_a.clone().into());
+        validator.token("token_b", &token_b.clone().into());
+        validator.finish()?;
+    }
+
+    let twap = ctx
+        .oracle
+        .lock()
+        .unwrap()
+        .twap(&token_a, &token_b, query.window)
+        .map_err(|e| AppError(StatusCode::NOT_FOUND, e))?;
+    Ok(Json(twap))
+}
+
+// --------------------------------------------------------
+//   Batch
+// --------------------------------------------------------
+
+#[utoipa::path(
+    post,
+    path = "/api/batch",
+    params(IdempotencyKey),
+    request_body = BatchRequest,
+    responses(
+        (status = 200, description = "Batch transaction submitted", body = TxHashResponse),
+        (status = 400, description = "Invalid request", body = ValidationErrors),
+        (status = 409, description = "Identity already registered", body = ValidationErrors),
+        (status = 422, description = "Transaction would fail on-chain", body = ValidationErrors),
+        (status = 500, description = "Transaction rejected or not submitted", body = String),
+    )
+)]
+async fn batch(
+    State(ctx): State<RouterCtx>,
+    Json(payload): Json<BatchRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    ctx.validate(&payload).await?;
+    let tx_hash = do_batch(ctx, payload).await?;
+    Ok(Json(TxHashResponse { tx_hash }))
+}
+
+// --------------------------------------------------------
+//   Simulate
+// --------------------------------------------------------
+
+#[utoipa::path(
+    post,
+    path = "/api/simulate",
+    request_body = SimulateRequest,
+    responses(
+        (status = 200, description = "State changes of the transaction", body = SimulateResponse),
+        (status = 400, description = "Invalid request", body = ValidationErrors),
+        (status = 422, description = "Transaction fails on the current states", body = ValidationErrors),
+    )
+)]
+async fn simulate(
+    State(ctx): State<RouterCtx>,
+    Json(payload): Json<SimulateRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    ctx.validate(&payload).await?;
+    let mut app = ctx.lock_app().await;
+    Ok(Json(simulate::simulate(&mut app, &payload)?))
+}
+
+// --------------------------------------------------------
+//   Transaction status
+// --------------------------------------------------------
+
+#[utoipa::path(
+    get,
+    path = "/api/tx/{tx_hash}",
+    params(("tx_hash" = String, Path, description = "Hash of the blob transaction")),
+    responses(
+        (status = 200, body = TxStatusResponse),
+        (status = 404, description = "Transaction unknown to the indexer", body = String),
+    )
+)]
+async fn tx_status(
+    State(ctx): State<RouterCtx>,
+    Path(tx_hash): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let tx_hash = TxHash(tx_hash);
+    let tx = ctx
+        .indexer
+        .get_transaction_with_hash(&tx_hash)
+        .await
+        .map_err(|e| AppError(StatusCode::NOT_FOUND, e))?;
+    Ok(Json(TxStatusResponse {
+        tx_hash,
+        status: tx.transaction_status,
+    }))
+}
+
+// --------------------------------------------------------
+//   History
+// --------------------------------------------------------
+
+#[utoipa::path(
+    get,
+    path = "/api/accounts/{identity}/history",
+    params(
+        ("identity" = String, Path, description = "Full identity, e.g. `bob.hydentity`"),
+        HistoryQuery,
+    ),
+    responses(
+        (status = 200, description = "Transactions involving the identity, newest first", body = HistoryResponse),
+    )
+)]
+async fn account_history(
+    State(ctx): State<RouterCtx>,
+    Path(identity): Path<String>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    Ok(Json(history::history(&ctx, &identity, &query).await))
+}
+
+// --------------------------------------------------------
+//      Register
+// --------------------------------------------------------
+
+#[utoipa::path(
+    post,
+    path = "/api/register",
+    params(IdempotencyKey),
+    request_body = RegisterRequest,
+    responses(
+        (status = 200, description = "Registration submitted", body = TxHashResponse),
+        (status = 400, description = "Invalid request", body = ValidationErrors),
+        (status = 409, description = "Identity already registered", body = ValidationErrors),
+        (status = 500, description = "Transaction rejected or not submitted", body = String),
+    )
+)]
 async fn register(
     State(ctx): State<RouterCtx>,
     Json(payload): Json<RegisterRequest>,
 ) -> Result<impl IntoResponse, AppError> {
+    ctx.validate(&payload).await?;
     let RegisterRequest { username, password } = payload;
 
     let tx_hash = do_register(ctx, username, password).await?;
-    Ok(Json(tx_hash))
+    Ok(Json(TxHashResponse { tx_hash }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/identity/{name}",
+    params(("name" = String, Path, description = "Identity, e.g. `bob.hydentity` or `bob`")),
+    responses(
+        (status = 200, body = IdentityResponse),
+        (status = 400, description = "Invalid identity", body = ValidationErrors),
+    )
+)]
+async fn identity(
+    State(ctx): State<RouterCtx>,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let app = ctx.lock_app().await;
+    let identity = if name.contains('.') {
+        name
+    } else {
+        format!(
+            "{name}.{}",
+            app.identities.default_provider().contract_name()
+        )
+    };
+    let mut validator = Validator::new(&app);
+    validator.identity("name", &identity);
+    validator.finish()?;
+
+    Ok(Json(app.identity(identity.into())))
 }
 
 // --------------------------------------------------------
 // --------------------------------------------------------
 
+#[instrument(skip_all, fields(
+    operation = "register",
+    identity = username.0.as_str(),
+    tx_hash = Empty,
+))]
 async fn do_register(
     ctx: RouterCtx,
     username: Identity,
     password: String,
 ) -> Result<TxHash, AppError> {
-    let mut app = ctx.app.lock_owned().await;
-    let mut transaction = ProvableBlobTx::new(username);
+    let mut app = ctx.lock_app().await;
+    let mut transaction = ProvableBlobTx::new(username.clone());
 
     app.register_identity(&mut transaction, password)?;
 
-    app.send(transaction).await
+    let tx_hash = app
+        .send(transaction, &ctx.history, move |tx_hash| {
+            vec![history::entry(
+                tx_hash,
+                &username,
+                &Operation::Register,
+                None,
+            )]
+        })
+        .await?;
+    Ok(tx_hash)
 }
 
+#[instrument(skip_all, fields(
+    operation = "transfer",
+    identity = identity.0.as_str(),
+    token = token.0.as_str(),
+    tx_hash = Empty,
+))]
 async fn do_transfer(
     ctx: RouterCtx,
     identity: Identity,
@@ -300,15 +1183,74 @@ async fn do_transfer(
     token: ContractName,
     amount: u128,
 ) -> Result<TxHash, AppError> {
-    let mut app = ctx.app.lock_owned().await;
-    let mut transaction = ProvableBlobTx::new(identity);
+    let mut app = ctx.lock_app().await;
+    let operation = Operation::Transfer {
+        recipient,
+        token,
+        amount,
+    };
 
+    let mut preflight = Preflight::new(&app);
+    preflight.check(&identity, &operation);
+    preflight.finish()?;
+
+    let mut transaction = ProvableBlobTx::new(identity.clone());
     app.verify_identity(&mut transaction, password)?;
-    app.transfer(&mut transaction, token, recipient, amount)?;
+    app.add_operation(&mut transaction, operation.clone(), None)?;
 
-    app.send(transaction).await
+    let tx_hash = app
+        .send(transaction, &ctx.history, move |tx_hash| {
+            vec![history::entry(tx_hash, &identity, &operation, None)]
+        })
+        .await?;
+    Ok(tx_hash)
 }
 
+#[instrument(skip_all, fields(
+    operation = "transfer_from",
+    identity = identity.0.as_str(),
+    token = token.0.as_str(),
+    tx_hash = Empty,
+))]
+async fn do_transfer_from(
+    ctx: RouterCtx,
+    identity: Identity,
+    password: String,
+    owner: String,
+    recipient: String,
+    token: ContractName,
+    amount: u128,
+) -> Result<TxHash, AppError> {
+    let mut app = ctx.lock_app().await;
+    let operation = Operation::TransferFrom {
+        owner,
+        recipient,
+        token,
+        amount,
+    };
+
+    let mut preflight = Preflight::new(&app);
+    preflight.check(&identity, &operation);
+    preflight.finish()?;
+
+    let mut transaction = ProvableBlobTx::new(identity.clone());
+    app.verify_identity(&mut transaction, password)?;
+    app.add_operation(&mut transaction, operation.clone(), None)?;
+
+    let tx_hash = app
+        .send(transaction, &ctx.history, move |tx_hash| {
+            vec![history::entry(tx_hash, &identity, &operation, None)]
+        })
+        .await?;
+    Ok(tx_hash)
+}
+
+#[instrument(skip_all, fields(
+    operation = "approve",
+    identity = identity.0.as_str(),
+    token = token.0.as_str(),
+    tx_hash = Empty,
+))]
 async fn do_approve(
     ctx: RouterCtx,
     identity: Identity,
@@ -317,16 +1259,33 @@ async fn do_approve(
     token: ContractName,
     amount: u128,
 ) -> Result<TxHash, AppError> {
-    let mut app = ctx.app.lock_owned().await;
-    let mut transaction = ProvableBlobTx::new(identity);
+    let mut app = ctx.lock_app().await;
+    let operation = Operation::Approve {
+        spender,
+        token,
+        amount,
+    };
+    let mut transaction = ProvableBlobTx::new(identity.clone());
 
     app.verify_identity(&mut transaction, password)?;
 
-    app.approve(&mut transaction, token, spender, amount)?;
+    app.add_operation(&mut transaction, operation.clone(), None)?;
 
-    app.send(transaction).await
+    let tx_hash = app
+        .send(transaction, &ctx.history, move |tx_hash| {
+            vec![history::entry(tx_hash, &identity, &operation, None)]
+        })
+        .await?;
+    Ok(tx_hash)
 }
 
+#[instrument(skip_all, fields(
+    operation = "swap",
+    identity = identity.0.as_str(),
+    token_a = token_a.0.as_str(),
+    token_b = token_b.0.as_str(),
+    tx_hash = Empty,
+))]
 async fn do_swap(
     ctx: RouterCtx,
     identity: Identity,
@@ -335,16 +1294,75 @@ async fn do_swap(
     token_b: ContractName,
     amount: u128,
 ) -> Result<TxHash, AppError> {
-    let mut app = ctx.app.lock_owned().await;
-    let mut transaction = ProvableBlobTx::new(identity);
+    let mut app = ctx.lock_app().await;
+    let operation = Operation::Swap {
+        token_a,
+        token_b,
+        amount,
+    };
+
+    let mut preflight = Preflight::new(&app);
+    let amount_b = preflight.check(&identity, &operation);
+    preflight.finish()?;
 
+    let mut transaction = ProvableBlobTx::new(identity.clone());
     app.verify_identity(&mut transaction, password)?;
-    app.swap(&mut transaction, token_a, token_b, amount).await?;
+    app.add_operation(&mut transaction, operation.clone(), amount_b)?;
+
+    let tx_hash = app
+        .send(transaction, &ctx.history, move |tx_hash| {
+            vec![history::entry(tx_hash, &identity, &operation, amount_b)]
+        })
+        .await?;
+    Ok(tx_hash)
+}
+
+#[instrument(skip_all, fields(
+    operation = "batch",
+    identity = request.username.0.as_str(),
+    operations = request.operations.len(),
+    tx_hash = Empty,
+))]
+async fn do_batch(ctx: RouterCtx, request: BatchRequest) -> Result<TxHash, AppError> {
+    let BatchRequest {
+        username: identity,
+        password,
+        operations,
+    } = request;
+    let mut app = ctx.lock_app().await;
+
+    let mut preflight = Preflight::new(&app);
+    let mut amounts_out = vec![];
+    for (i, operation) in operations.iter().enumerate() {
+        preflight.operation(i);
+        amounts_out.push(preflight.check(&identity, operation));
+    }
+    preflight.finish()?;
+
+    let transaction =
+        app.build_transaction(identity.clone(), password, &operations, &amounts_out)?;
 
-    app.send(transaction).await
+    let tx_hash = app
+        .send(transaction, &ctx.history, move |tx_hash| {
+            operations
+                .iter()
+                .zip(amounts_out)
+                .map(|(operation, amount_out)| {
+                    history::entry(tx_hash, &identity, operation, amount_out)
+                })
+                .collect()
+        })
+        .await?;
+    Ok(tx_hash)
 }
 
+/// Contracts the server keeps a state of, dispatched by [`States`] on these exact names.
+
+/// Manifest tokens the server keeps a state of, see [`States`].
+const TOKENS: [&str; 2] = ["hyllar", "hyllar2"];
+
 contract_states!(
+    #[derive(Clone)]
     pub struct States {
         pub hyllar: HyllarToken,
         pub hyllar2: HyllarToken,
@@ -353,33 +1371,234 @@ contract_states!(
     }
 );
 
+impl States {
+    /// State of a manifest token.
+    fn token(&self, token: &ContractName) -> Option<&HyllarToken> {
+        match token.0.as_str() {
+            "hyllar" => Some(&self.hyllar),
+            "hyllar2" => Some(&self.hyllar2),
+            _ => None,
+        }
+    }
+}
+
 struct HyleOofCtx {
     executor: TxExecutor<States>,
     client: Arc<NodeApiHttpClient>,
     prover: Arc<Prover>,
-    hydentity_cn: ContractName,
+    identities: IdentityProviders,
     amm_cn: ContractName,
+    faucet: Identity,
+    deployment: Deployment,
+    /// When the local states last matched the ones of the indexer.
+    synced_at: SystemTime,
+    /// Transactions sent since, not settled yet according to the indexer.
+    unsettled: Arc<SyncMutex<Vec<TxHash>>>,
+    /// AMM state last fetched from the indexer, which only includes settled swaps.
+    settled_amm: AmmState,
 }
 
 impl HyleOofCtx {
-    async fn send(&mut self, transaction: ProvableBlobTx) -> Result<TxHash, AppError> {
+    /// Sends `transaction`, recording the `history` entries built from its hash once the node
+    /// accepted it.
+    async fn send(
+        &mut self,
+        transaction: ProvableBlobTx,
+        history: &Arc<SyncMutex<History>>,
+        entries: impl FnOnce(&TxHash) -> Vec<HistoryEntry> + Send + 'static,
+    ) -> Result<TxHash, AppError> {
+        if let Some(blob) = transaction
+            .blobs
+            .iter()
+            .find(|blob| self.deployment.is_read_only(&blob.contract_name))
+        {
+            return Err(AppError(
+                StatusCode::SERVICE_UNAVAILABLE,
+                anyhow!(
+                    "Contract {} is outdated and served in read-only mode",
+                    blob.contract_name
+                ),
+            ));
+        }
+
         let blob_tx = BlobTransaction::new(transaction.identity.clone(), transaction.blobs.clone());
 
         let proof_tx_builder = self.executor.process(transaction)?;
+        // Unsettled from now on, as the local states include it.
+        let local_hash = blob_tx.hash();
+        self.unsettled.lock().unwrap().push(local_hash.clone());
+        self.record_state_metrics();
 
-        let tx_hash = self.client.send_tx_blob(&blob_tx).await?;
+        // Sent from a task of its own, so that a request cancelled on timeout never leaves a
+        // sequenced transaction without its proofs, history or `Idempotency-Key`: a retry of
+        // the cancelled request is replayed instead of sent again.
+        let client = self.client.clone();
+        let prover = self.prover.clone();
+        let unsettled = self.unsettled.clone();
+        let history = history.clone();
+        let submission = Submission::start();
+        let tx_hash = tokio::spawn(
+            async move {
+                let tx_hash = match client.send_tx_blob(&blob_tx).await {
+                    Ok(tx_hash) => tx_hash,
+                    Err(e) => {
+                        metrics::BLOB_SUBMISSION_ERRORS.inc();
+                        unsettled
+                            .lock()
+                            .unwrap()
+                            .retain(|tx_hash| *tx_hash != local_hash);
+                        submission.fail();
+                        return Err(e);
+                    }
+                };
+                Span::current().record("tx_hash", tx_hash.0.as_str());
+                info!("Blob transaction submitted");
+                history::record(&history, entries(&tx_hash));
+                submission.complete(&tx_hash).await;
 
-        self.prover.add(proof_tx_builder).await;
+                prover.add(tx_hash.clone(), proof_tx_builder).await;
+                anyhow::Ok(tx_hash)
+            }
+            .in_current_span(),
+        )
+        .await??;
 
         Ok(tx_hash)
     }
 
+    /// Settled pairs of the AMM, named after the manifest tokens.
+    fn pools(&self) -> Result<Vec<pools::PoolReserves>> {
+        pools::reserves(&self.settled_amm)
+    }
+
+    /// Exports the AMM reserves and faucet balances of the local states.
+    fn record_state_metrics(&self) {
+        match pools::reserves(&self.executor.amm) {
+            Ok(pools) => {
+                for pool in pools {
+                    let pair = format!("{}/{}", pool.token_a, pool.token_b);
+                    metrics::AMM_RESERVES
+                        .with_label_values(&[&pair, &pool.token_a])
+                        .set(pool.reserve_a as f64);
+                    metrics::AMM_RESERVES
+                        .with_label_values(&[&pair, &pool.token_b])
+                        .set(pool.reserve_b as f64);
+                }
+            }
+            Err(e) => error!("Failed to read AMM reserves: {e:#}"),
+        }
+
+        for (token, balance) in self.faucet_balances() {
+            if let Some(balance) = balance {
+                metrics::FAUCET_BALANCE
+                    .with_label_values(&[&token.0])
+                    .set(balance as f64);
+            }
+        }
+    }
+
+    /// Faucet balance of each token, `None` when the faucet never held any.
+    fn faucet_balances(&self) -> Vec<(ContractName, Option<u128>)> {
+        TOKENS
+            .into_iter()
+            .filter_map(|token| {
+                let token = ContractName::from(token);
+                let state = self.token_state(&token)?;
+                let contract = HyllarTokenContract::init(state.clone(), self.faucet.clone());
+                Some((token, contract.balance_of(&self.faucet.0).ok()))
+            })
+            .collect()
+    }
+
+    /// Local state of a manifest token.
+    fn token_state(&self, token: &ContractName) -> Option<&HyllarToken> {
+        self.executor.token(token)
+    }
+
+    fn is_registered(&self, identity: &str) -> bool {
+        self.identities.is_registered(&self.executor, identity)
+    }
+
+    fn identity(&self, identity: Identity) -> IdentityResponse {
+        let provider = self.identities.for_identity(&identity.0).ok();
+        IdentityResponse {
+            registered: self.is_registered(&identity.0),
+            nonce: provider.and_then(|provider| provider.nonce(&self.executor, &identity.0)),
+            rotation_supported: provider.is_some_and(|provider| provider.supports_rotation()),
+            identity,
+        }
+    }
+
+    /// Builds a single transaction running `operations` from `identity`. Swaps receive their
+    /// entry of `amounts_out`, quoted by a [`Preflight`] on the states left by the operations
+    /// before them.
+    fn build_transaction(
+        &mut self,
+        identity: Identity,
+        password: String,
+        operations: &[Operation],
+        amounts_out: &[Option<u128>],
+    ) -> Result<ProvableBlobTx> {
+        let mut transaction = ProvableBlobTx::new(identity);
+        if let [Operation::Register] = operations {
+            self.register_identity(&mut transaction, password)?;
+            return Ok(transaction);
+        }
+
+        self.verify_identity(&mut transaction, password)?;
+        for (operation, amount_out) in operations.iter().cloned().zip(amounts_out) {
+            self.add_operation(&mut transaction, operation, *amount_out)?;
+        }
+        Ok(transaction)
+    }
+
+    /// Adds the blobs of `operation`, sent from the identity of `transaction`. Swaps receive
+    /// `amount_out`, their quote.
+    fn add_operation(
+        &self,
+        transaction: &mut ProvableBlobTx,
+        operation: Operation,
+        amount_out: Option<u128>,
+    ) -> Result<()> {
+        match operation {
+            Operation::Transfer {
+                recipient,
+                token,
+                amount,
+            } => self.transfer(transaction, token, recipient, amount),
+            Operation::Approve {
+                spender,
+                token,
+                amount,
+            } => self.approve(transaction, token, spender, amount),
+            Operation::TransferFrom {
+                owner,
+                recipient,
+                token,
+                amount,
+            } => self.transfer_from(transaction, token, owner, recipient, amount),
+            Operation::Swap {
+                token_a,
+                token_b,
+                amount,
+            } => {
+                let amount_b = amount_out.context("swaps need a quote")?;
+                self.swap(transaction, token_a, token_b, amount, amount_b)
+            }
+            Operation::Register => {
+                bail!("register must be the only operation of its transaction")
+            }
+        }
+    }
+
     fn register_identity(
         &mut self,
         transaction: &mut ProvableBlobTx,
         password: String,
     ) -> Result<()> {
-        hydentity::client::register_identity(transaction, self.hydentity_cn.clone(), password)
+        self.identities
+            .for_identity(&transaction.identity.0)?
+            .register(transaction, password)
     }
 
     fn verify_identity(
@@ -387,16 +1606,13 @@ impl HyleOofCtx {
         transaction: &mut ProvableBlobTx,
         password: String,
     ) -> Result<()> {
-        hydentity::client::verify_identity(
-            transaction,
-            self.hydentity_cn.clone(),
-            &self.executor.hydentity,
-            password,
-        )
+        self.identities
+            .for_identity(&transaction.identity.0)?
+            .verify(transaction, &self.executor, password)
     }
 
     fn transfer(
-        &mut self,
+        &self,
         transaction: &mut ProvableBlobTx,
         token: ContractName,
         recipient: String,
@@ -405,8 +1621,22 @@ impl HyleOofCtx {
         hyllar::client::transfer(transaction, token, recipient, amount)
     }
 
+    /// Moves `amount` of `owner`'s tokens using the allowance given to the transaction
+    /// identity. The local state is updated when the transaction is processed, before it
+    /// settles on-chain.
+    fn transfer_from(
+        &self,
+        transaction: &mut ProvableBlobTx,
+        token: ContractName,
+        owner: String,
+        recipient: String,
+        amount: u128,
+    ) -> Result<()> {
+        hyllar::client::transfer_from(transaction, token, owner, recipient, amount)
+    }
+
     fn approve(
-        &mut self,
+        &self,
         transaction: &mut ProvableBlobTx,
         token: ContractName,
         spender: String,
@@ -415,19 +1645,18 @@ impl HyleOofCtx {
         hyllar::client::approve(transaction, token, spender, amount)
     }
 
-    pub async fn swap(
-        &mut self,
+    fn quote(&self, token_a: ContractName, token_b: ContractName, amount: u128) -> Result<u128> {
+        Self::get_paired_amount(&self.executor.amm, token_a.0, token_b.0, amount)
+    }
+
+    fn swap(
+        &self,
         transaction: &mut ProvableBlobTx,
         token_a: ContractName,
         token_b: ContractName,
         amount: u128,
+        amount_b: u128,
     ) -> Result<()> {
-        let amount_b = Self::get_paired_amount(
-            &self.executor.amm,
-            token_a.0.clone(),
-            token_b.0.clone(),
-            amount,
-        )?;
         amm::client::swap(
             transaction,
             self.amm_cn.clone(),
@@ -448,3 +1677,59 @@ impl HyleOofCtx {
         Ok(attr)
     }
 }
+
+#[cfg(test)]
+impl HyleOofCtx {
+    /// Context over the states of the bundled genesis, without any node behind it: the faucet
+    /// owns both tokens and the `amm` pool holds its reserves.
+    fn for_tests() -> Self {
+        let conf = Conf::defaults();
+        let faucet = conf.faucet.identity.clone();
+        let token = || {
+            let mut token = HyllarTokenContract::init(
+                HyllarToken::new(100_000_000_000, faucet.0.clone()),
+                faucet.clone(),
+            );
+            token.transfer("amm", 1_000_000_000).unwrap();
+            token.state().clone()
+        };
+        let pair = amm::UnorderedTokenPair::new("hyllar".to_string(), "hyllar2".to_string());
+        let states = States {
+            hyllar: token(),
+            hyllar2: token(),
+            hydentity: Hydentity::new(),
+            amm: AmmState::new([(pair, (1_000_000_000, 1_000_000_000))].into()),
+        };
+        let deployment = Deployment::default();
+        let node = Arc::new(NodeApiHttpClient {
+            url: conf.node_url().unwrap(),
+            reqwest_client: Client::new(),
+        });
+
+        HyleOofCtx {
+            settled_amm: states.amm.clone(),
+            executor: TxExecutorBuilder::new(states).build(),
+            client: node.clone(),
+            prover: Arc::new(Prover::new(node)),
+            identities: IdentityProviders::new(&conf),
+            amm_cn: "amm".into(),
+            faucet,
+            deployment,
+            synced_at: SystemTime::now(),
+            unsettled: Default::default(),
+        }
+    }
+
+    /// Registers `identity` in the local hydentity state.
+    fn register_for_tests(&mut self, identity: &str) {
+        let transaction = self
+            .build_transaction(
+                identity.into(),
+                "password".to_string(),
+                &[Operation::Register],
+                &[None],
+            )
+            .unwrap();
+        self.executor.process(transaction).unwrap();
+    }
+}
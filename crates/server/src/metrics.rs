@@ -0,0 +1,92 @@
+use std::sync::LazyLock;
+
+use prometheus::{
+    register_gauge_vec, register_histogram, register_histogram_vec, register_int_counter,
+    register_int_counter_vec, register_int_gauge, Encoder, GaugeVec, Histogram, HistogramVec,
+    IntCounter, IntCounterVec, IntGauge, TextEncoder,
+};
+
+pub static HTTP_REQUESTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "hyleoof_http_requests_total",
+        "HTTP requests handled, per route and status",
+        &["method", "route", "status"]
+    )
+    .unwrap()
+});
+
+pub static HTTP_REQUEST_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "hyleoof_http_request_duration_seconds",
+        "HTTP request latency, per route",
+        &["method", "route"]
+    )
+    .unwrap()
+});
+
+pub static PROVING_QUEUE_DEPTH: LazyLock<IntGauge> = LazyLock::new(|| {
+    register_int_gauge!(
+        "hyleoof_proving_queue_depth",
+        "Transactions waiting to be proven"
+    )
+    .unwrap()
+});
+
+pub static PROOF_DURATION: LazyLock<Histogram> = LazyLock::new(|| {
+    register_histogram!(
+        "hyleoof_proof_duration_seconds",
+        "Time spent generating a single proof",
+        vec![1.0, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0]
+    )
+    .unwrap()
+});
+
+pub static PROOF_FAILURES: LazyLock<IntCounter> = LazyLock::new(|| {
+    register_int_counter!(
+        "hyleoof_proof_failures_total",
+        "Proofs that could not be generated or sent to the node"
+    )
+    .unwrap()
+});
+
+pub static BLOB_SUBMISSION_ERRORS: LazyLock<IntCounter> = LazyLock::new(|| {
+    register_int_counter!(
+        "hyleoof_blob_submission_errors_total",
+        "Blob transactions rejected by the node"
+    )
+    .unwrap()
+});
+
+pub static APP_LOCK_WAIT: LazyLock<Histogram> = LazyLock::new(|| {
+    register_histogram!(
+        "hyleoof_app_lock_wait_seconds",
+        "Time spent waiting for the application state lock",
+        vec![0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 30.0]
+    )
+    .unwrap()
+});
+
+pub static AMM_RESERVES: LazyLock<GaugeVec> = LazyLock::new(|| {
+    register_gauge_vec!(
+        "hyleoof_amm_reserves",
+        "AMM reserves of a token in a pair, as seen by the server",
+        &["pair", "token"]
+    )
+    .unwrap()
+});
+
+pub static FAUCET_BALANCE: LazyLock<GaugeVec> = LazyLock::new(|| {
+    register_gauge_vec!(
+        "hyleoof_faucet_balance",
+        "Faucet balance per token, as seen by the server",
+        &["token"]
+    )
+    .unwrap()
+});
+
+/// Renders every registered metric in the Prometheus text format.
+pub fn render() -> anyhow::Result<String> {
+    let mut buffer = vec![];
+    TextEncoder::new().encode(&prometheus::gather(), &mut buffer)?;
+    Ok(String::from_utf8(buffer)?)
+}
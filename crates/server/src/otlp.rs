@@ -0,0 +1,23 @@
+use anyhow::Result;
+use opentelemetry::{trace::TracerProvider as _, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{
+    runtime,
+    trace::{Tracer, TracerProvider},
+    Resource,
+};
+
+/// Tracer exporting spans in batches to the OTLP collector at `endpoint`.
+pub fn tracer(endpoint: &str) -> Result<Tracer> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(endpoint)
+        .build()?;
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new([KeyValue::new("service.name", "hyleoof")]))
+        .build();
+    let tracer = provider.tracer("hyleoof");
+    opentelemetry::global::set_tracer_provider(provider);
+    Ok(tracer)
+}
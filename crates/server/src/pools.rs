@@ -0,0 +1,193 @@
+use amm::AmmState;
+use anyhow::{Context, Result};
+use borsh::BorshDeserialize;
+use hyleoof_api::PoolResponse;
+use serde::Serialize;
+
+use crate::history::History;
+
+/// Reserves of a single AMM pair.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PoolReserves {
+    pub token_a: String,
+    pub token_b: String,
+    pub reserve_a: u128,
+    pub reserve_b: u128,
+}
+
+/// Mirrors the borsh layout of `AmmState`, whose pairs are not exposed by the contract crate.
+#[derive(BorshDeserialize)]
+#[cfg_attr(test, derive(borsh::BorshSerialize))]
+struct AmmPairs {
+    pairs: std::collections::BTreeMap<(String, String), (u128, u128)>,
+}
+
+/// Lists the pairs of the AMM along with their reserves.
+pub fn reserves(state: &AmmState) -> Result<Vec<PoolReserves>> {
+    let AmmPairs { pairs } =
+        borsh::from_slice(&borsh::to_vec(state)?).context("decoding AMM pairs from its state")?;
+    Ok(pairs
+        .into_iter()
+        .map(
+            |((token_a, token_b), (reserve_a, reserve_b))| PoolReserves {
+                token_a,
+                token_b,
+                reserve_a,
+                reserve_b,
+            },
+        )
+        .collect())
+}
+
+impl PoolReserves {
+    /// The same pair with `token_a` and `token_b` swapped.
+    pub fn flipped(self) -> Self {
+        PoolReserves {
+            token_a: self.token_b,
+            token_b: self.token_a,
+            reserve_a: self.reserve_b,
+            reserve_b: self.reserve_a,
+        }
+    }
+
+    /// Amount of `token_b` one `token_a` is worth.
+    pub fn price_a_in_b(&self) -> Option<f64> {
+        (self.reserve_a > 0).then(|| self.reserve_b as f64 / self.reserve_a as f64)
+    }
+
+    pub fn price_b_in_a(&self) -> Option<f64> {
+        (self.reserve_b > 0).then(|| self.reserve_a as f64 / self.reserve_b as f64)
+    }
+}
+
+/// Pair of `token_a` and `token_b`, oriented as asked.
+pub fn find(pools: &[PoolReserves], token_a: &str, token_b: &str) -> Option<PoolReserves> {
+    pools.iter().find_map(|pool| {
+        if pool.token_a == token_a && pool.token_b == token_b {
+            Some(pool.clone())
+        } else if pool.token_a == token_b && pool.token_b == token_a {
+            Some(pool.clone().flipped())
+        } else {
+            None
+        }
+    })
+}
+
+/// Value of `amount` of `token` in `reference`, at the spot price of their pair.
+pub fn value_in(pools: &[PoolReserves], token: &str, amount: u128, reference: &str) -> Option<f64> {
+    if token == reference {
+        return Some(amount as f64);
+    }
+    let price = find(pools, token, reference)?.price_a_in_b()?;
+    Some(amount as f64 * price)
+}
+
+/// Analytics of `pool`, whose reserves are valued against the other `pools` of the AMM and
+/// whose volume comes from the swaps of the `history` since `since`.
+pub fn analytics(
+    pools: &[PoolReserves],
+    pool: PoolReserves,
+    reference: &str,
+    history: &History,
+    since: u64,
+) -> PoolResponse {
+    let volume = history.swap_volume(&pool.token_a, &pool.token_b, since);
+    let tvl = value_in(pools, &pool.token_a, pool.reserve_a, reference)
+        .zip(value_in(pools, &pool.token_b, pool.reserve_b, reference))
+        .map(|(a, b)| a + b);
+    PoolResponse {
+        price_a_in_b: pool.price_a_in_b(),
+        price_b_in_a: pool.price_b_in_a(),
+        k: pool.reserve_a.checked_mul(pool.reserve_b),
+        reference_token: reference.to_string(),
+        tvl,
+        volume_24h_a: volume.amount_a,
+        volume_24h_b: volume.amount_b,
+        swaps_24h: volume.swaps,
+        token_a: pool.token_a,
+        token_b: pool.token_b,
+        reserve_a: pool.reserve_a,
+        reserve_b: pool.reserve_b,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amm::UnorderedTokenPair;
+
+    use super::*;
+
+    fn amm() -> AmmState {
+        let pair = |a: &str, b: &str| UnorderedTokenPair::new(a.to_string(), b.to_string());
+        AmmState::new(
+            [
+                (pair("hyllar", "hyllar2"), (1000, 4000)),
+                (pair("hyllar", "hyllar3"), (500, 0)),
+            ]
+            .into(),
+        )
+    }
+
+    fn pool(token_a: &str, token_b: &str, reserve_a: u128, reserve_b: u128) -> PoolReserves {
+        PoolReserves {
+            token_a: token_a.to_string(),
+            token_b: token_b.to_string(),
+            reserve_a,
+            reserve_b,
+        }
+    }
+
+    #[test]
+    fn pairs_round_trip_through_the_amm_state() {
+        let state = amm();
+        let pairs: AmmPairs = borsh::from_slice(&borsh::to_vec(&state).unwrap()).unwrap();
+        assert_eq!(
+            borsh::to_vec(&pairs).unwrap(),
+            borsh::to_vec(&state).unwrap()
+        );
+
+        assert_eq!(
+            reserves(&state).unwrap(),
+            [
+                pool("hyllar", "hyllar2", 1000, 4000),
+                pool("hyllar", "hyllar3", 500, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn pairs_are_found_and_valued_in_either_order() {
+        let pools = reserves(&amm()).unwrap();
+        assert_eq!(
+            find(&pools, "hyllar2", "hyllar"),
+            Some(pool("hyllar2", "hyllar", 4000, 1000))
+        );
+        assert_eq!(find(&pools, "hyllar2", "hyllar3"), None);
+
+        assert_eq!(value_in(&pools, "hyllar", 10, "hyllar"), Some(10.0));
+        assert_eq!(value_in(&pools, "hyllar", 10, "hyllar2"), Some(40.0));
+        assert_eq!(value_in(&pools, "hyllar2", 10, "hyllar"), Some(2.5));
+        // An empty reserve gives no price
+        assert_eq!(value_in(&pools, "hyllar3", 10, "hyllar"), None);
+    }
+
+    #[test]
+    fn analytics_value_the_pool_in_the_reference_token() {
+        let path = std::env::temp_dir().join(format!("hyleoof-pools-{}.jsonl", std::process::id()));
+        let history = History::open(&path).unwrap();
+        let pools = reserves(&amm()).unwrap();
+
+        let response = analytics(&pools, pools[0].clone(), "hyllar", &history, 0);
+        assert_eq!(response.price_a_in_b, Some(4.0));
+        assert_eq!(response.price_b_in_a, Some(0.25));
+        assert_eq!(response.k, Some(4_000_000));
+        assert_eq!(response.tvl, Some(2000.0));
+        assert_eq!(response.swaps_24h, 0);
+
+        // An empty reserve can be neither priced nor valued
+        let response = analytics(&pools, pools[1].clone(), "hyllar", &history, 0);
+        assert_eq!(response.price_b_in_a, None);
+        assert_eq!(response.tvl, None);
+        let _ = std::fs::remove_file(path);
+    }
+}
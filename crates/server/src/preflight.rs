@@ -0,0 +1,147 @@
+use axum::http::StatusCode;
+use client_sdk::transaction_builder::{ProvableBlobTx, TxExecutor, TxExecutorBuilder};
+use hyleoof_api::{FieldError, Operation, ValidationErrors};
+use sdk::Identity;
+
+use crate::{utils::AppError, HyleOofCtx, States};
+
+/// Processes the operations of a transaction one after the other on a copy of the local
+/// states, running the same contract code as on-chain, so that transactions bound to fail are
+/// rejected before being sent to the node.
+pub struct Preflight<'a> {
+    app: &'a HyleOofCtx,
+    executor: TxExecutor<States>,
+    /// Prefix of the field errors are reported on, e.g. `operations[2].`.
+    prefix: String,
+    errors: Vec<FieldError>,
+}
+
+impl<'a> Preflight<'a> {
+    pub fn new(app: &'a HyleOofCtx) -> Self {
+        Preflight {
+            app,
+            executor: TxExecutorBuilder::new((*app.executor).clone()).build(),
+            prefix: String::new(),
+            errors: vec![],
+        }
+    }
+
+    /// Reports the following errors on the `index`th operation of a batch.
+    pub fn operation(&mut self, index: usize) {
+        self.prefix = format!("operations[{index}].");
+    }
+
+    fn error(&mut self, message: String) {
+        self.errors.push(FieldError {
+            field: format!("{}amount", self.prefix),
+            message,
+        });
+    }
+
+    /// Runs `operation` of `identity` on the states left by the operations checked so far.
+    /// Returns the amount swaps receive, quoted on those states.
+    pub fn check(&mut self, identity: &Identity, operation: &Operation) -> Option<u128> {
+        let amount_out = match operation {
+            // Registrations are checked by the validation of the request.
+            Operation::Register => return None,
+            Operation::Swap {
+                token_a,
+                token_b,
+                amount,
+            } => {
+                match HyleOofCtx::get_paired_amount(
+                    &self.executor.amm,
+                    token_a.0.clone(),
+                    token_b.0.clone(),
+                    *amount,
+                ) {
+                    Ok(amount_b) => Some(amount_b),
+                    Err(e) => {
+                        self.error(format!("{e:#}"));
+                        return None;
+                    }
+                }
+            }
+            _ => None,
+        };
+
+        let mut transaction = ProvableBlobTx::new(identity.clone());
+        let result = self
+            .app
+            .add_operation(&mut transaction, operation.clone(), amount_out)
+            .and_then(|()| self.executor.process(transaction).map(|_| ()));
+        if let Err(e) = result {
+            self.error(format!("{e:#}"));
+        }
+        amount_out
+    }
+
+    pub fn finish(self) -> Result<(), AppError> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(AppError(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                ValidationErrors {
+                    errors: self.errors,
+                }
+                .into(),
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn swap(amount: u128) -> Operation {
+        Operation::Swap {
+            token_a: "hyllar".into(),
+            token_b: "hyllar2".into(),
+            amount,
+        }
+    }
+
+    #[tokio::test]
+    async fn swaps_are_quoted_on_the_states_left_by_previous_operations() {
+        let app = HyleOofCtx::for_tests();
+        let faucet = app.faucet.clone();
+        let mut preflight = Preflight::new(&app);
+
+        let approve = Operation::Approve {
+            spender: "amm".to_string(),
+            token: "hyllar".into(),
+            amount: 1_000_000,
+        };
+        assert_eq!(preflight.check(&faucet, &approve), None);
+        let first = preflight.check(&faucet, &swap(1000)).unwrap();
+        let second = preflight.check(&faucet, &swap(1000)).unwrap();
+        assert!(second < first, "{second} >= {first}");
+        assert!(preflight.finish().is_ok());
+    }
+
+    #[tokio::test]
+    async fn failing_operations_are_reported_on_their_amount() {
+        let app = HyleOofCtx::for_tests();
+        let mut preflight = Preflight::new(&app);
+
+        preflight.operation(0);
+        let transfer = Operation::Transfer {
+            recipient: "alice.hydentity".to_string(),
+            token: "hyllar".into(),
+            amount: 1,
+        };
+        preflight.check(&"bob.hydentity".into(), &transfer);
+        preflight.operation(1);
+        // Not approved
+        preflight.check(&app.faucet, &swap(1000));
+
+        let fields: Vec<_> = preflight.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, ["operations[0].amount", "operations[1].amount"]);
+        assert_eq!(
+            preflight.finish().unwrap_err().0,
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+    }
+}
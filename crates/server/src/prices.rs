@@ -0,0 +1,375 @@
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::{Arc, Mutex as SyncMutex},
+    time::{Duration, SystemTime},
+};
+
+use amm::AmmState;
+use anyhow::Result;
+use hyleoof_api::{Candle, HistoryEntry, HistoryOperation};
+use serde::{Deserialize, Serialize};
+use tracing::{error, warn};
+
+use crate::{
+    pools::{self, PoolReserves},
+    store::{load_json, save_json},
+    RouterCtx,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    /// Swap submitted by this server, recorded once the indexer reports it successful at the
+    /// price it executed at, along with the reserves of the indexer state then.
+    Swap,
+    /// Reserves seen in the AMM state of the indexer.
+    Indexer,
+}
+
+/// Observation of a pair.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PricePoint {
+    /// Seconds since the unix epoch.
+    pub timestamp: u64,
+    pub source: Source,
+    /// Price of `token_a` in `token_b`: `reserve_b / reserve_a` for indexer observations, the
+    /// amounts exchanged for swaps.
+    pub price: f64,
+    pub reserve_a: u128,
+    pub reserve_b: u128,
+    /// Amounts swapped, zero for indexer observations.
+    pub volume_a: u128,
+    pub volume_b: u128,
+}
+
+impl PricePoint {
+    /// The same observation with `token_a` and `token_b` swapped.
+    fn flipped(&self) -> Self {
+        PricePoint {
+            price: 1.0 / self.price,
+            reserve_a: self.reserve_b,
+            reserve_b: self.reserve_a,
+            volume_a: self.volume_b,
+            volume_b: self.volume_a,
+            ..self.clone()
+        }
+    }
+}
+
+/// Price observations of each AMM pair, oldest first.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PriceStore {
+    #[serde(skip)]
+    path: PathBuf,
+    #[serde(skip)]
+    retention: Duration,
+    /// Keyed by `<token_a>/<token_b>` with the tokens in alphabetical order, and oriented
+    /// accordingly.
+    pairs: BTreeMap<String, Vec<PricePoint>>,
+    /// Held while writing the store, so that snapshots reach the disk in order.
+    #[serde(skip)]
+    saving: Arc<tokio::sync::Mutex<()>>,
+}
+
+/// Key of a pair, and whether `token_a` and `token_b` are in the reverse order of the key.
+pub fn pair_key(token_a: &str, token_b: &str) -> (String, bool) {
+    if token_a <= token_b {
+        (format!("{token_a}/{token_b}"), false)
+    } else {
+        (format!("{token_b}/{token_a}"), true)
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+impl PriceStore {
+    pub fn open(path: PathBuf, retention: Duration) -> Result<Self> {
+        let mut store: PriceStore = load_json(&path)?;
+        store.path = path;
+        store.retention = retention;
+        Ok(store)
+    }
+
+    /// Last observation of the pair, oriented as asked.
+    fn last(&self, token_a: &str, token_b: &str) -> Option<PricePoint> {
+        let (key, flipped) = pair_key(token_a, token_b);
+        let point = self.pairs.get(&key)?.last()?;
+        Some(if flipped {
+            point.flipped()
+        } else {
+            point.clone()
+        })
+    }
+
+    /// Adds an observation of `token_a` in `token_b` in timestamp order, dropping the ones older
+    /// than the retention.
+    fn push(&mut self, token_a: &str, token_b: &str, point: PricePoint) {
+        let (key, flipped) = pair_key(token_a, token_b);
+        let oldest = now().saturating_sub(self.retention.as_secs());
+        let points = self.pairs.entry(key).or_default();
+        points.retain(|point| point.timestamp >= oldest);
+        let at = points.partition_point(|p| p.timestamp <= point.timestamp);
+        points.insert(at, if flipped { point.flipped() } else { point });
+    }
+
+    /// Records the settled swaps of `entries` at the price given by the amounts they exchanged,
+    /// each at the timestamp it comes with, along with the reserves of their pair in `pools`.
+    /// Returns whether anything was recorded, to be written by [`save`].
+    pub fn record_swaps(
+        &mut self,
+        pools: &[PoolReserves],
+        entries: &[(u64, HistoryEntry)],
+    ) -> bool {
+        let mut recorded = false;
+        for (timestamp, entry) in entries {
+            let (HistoryOperation::Swap, [token_in, token_out], [amount_in, amount_out]) = (
+                entry.operation,
+                entry.tokens.as_slice(),
+                entry.amounts.as_slice(),
+            ) else {
+                continue;
+            };
+            let Some(pool) = pools::find(pools, &token_in.0, &token_out.0) else {
+                continue;
+            };
+            if *amount_in == 0 {
+                continue;
+            }
+            self.push(
+                &token_in.0,
+                &token_out.0,
+                PricePoint {
+                    timestamp: *timestamp,
+                    source: Source::Swap,
+                    price: *amount_out as f64 / *amount_in as f64,
+                    price,
+                    reserve_a: pool.reserve_a,
+                    reserve_b: pool.reserve_b,
+                    volume_a: *amount_in,
+                    volume_b: *amount_out,
+                },
+            );
+            recorded = true;
+        }
+        recorded
+    }
+
+    /// Records the spot price of the pairs whose reserves changed since their last observation.
+    /// Returns whether anything was recorded, to be written by [`save`].
+    pub fn observe(&mut self, pools: &[PoolReserves]) -> bool {
+        let mut recorded = false;
+        for pool in pools {
+            let unchanged = self.last(&pool.token_a, &pool.token_b).is_some_and(|last| {
+                (last.reserve_a, last.reserve_b) == (pool.reserve_a, pool.reserve_b)
+            });
+            let Some(price) = pool.price_a_in_b().filter(|_| !unchanged) else {
+                continue;
+            };
+            self.push(
+                &pool.token_a,
+                &pool.token_b,
+                PricePoint {
+                    timestamp: now(),
+                    source: Source::Indexer,
+                    price,
+                    reserve_a: pool.reserve_a,
+                    reserve_b: pool.reserve_b,
+                    volume_a: 0,
+                    volume_b: 0,
+                },
+            );
+            recorded = true;
+        }
+        recorded
+    }
+
+    /// Candles of `token_a` in `token_b` of `interval` seconds, between `from` (included) and
+    /// `to` (excluded).
+    pub fn candles(
+        &self,
+        token_a: &str,
+        token_b: &str,
+        interval: u64,
+        from: u64,
+        to: u64,
+    ) -> Vec<Candle> {
+        let (key, flipped) = pair_key(token_a, token_b);
+        let points = self.pairs.get(&key).map(Vec::as_slice).unwrap_or_default();
+
+        let mut candles: Vec<Candle> = vec![];
+        for point in points
+            .iter()
+            .filter(|point| (from..to).contains(&point.timestamp))
+        {
+            let point = if flipped {
+                point.flipped()
+            } else {
+                point.clone()
+            };
+            let start = point.timestamp - point.timestamp % interval;
+            match candles.last_mut() {
+                Some(candle) if candle.start == start => {
+                    candle.high = candle.high.max(point.price);
+                    candle.low = candle.low.min(point.price);
+                    candle.close = point.price;
+                    candle.volume_a = candle.volume_a.saturating_add(point.volume_a);
+                    candle.volume_b = candle.volume_b.saturating_add(point.volume_b);
+                }
+                _ => candles.push(Candle {
+                    start,
+                    open: point.price,
+                    high: point.price,
+                    low: point.price,
+                    close: point.price,
+                    volume_a: point.volume_a,
+                    volume_b: point.volume_b,
+                }),
+            }
+        }
+        candles
+    }
+}
+
+/// Writes `store` to disk from a blocking task, without holding its lock during the write.
+pub async fn save(store: &SyncMutex<PriceStore>) -> Result<()> {
+    let saving = store.lock().unwrap().saving.clone();
+    let _saving = saving.lock().await;
+    let (path, snapshot) = {
+        let store = store.lock().unwrap();
+        (store.path.clone(), serde_json::to_value(&*store)?)
+    };
+    tokio::task::spawn_blocking(move || save_json(&path, &snapshot)).await?
+}
+
+/// Periodically records the reserves of the AMM state of the indexer, which include the swaps
+/// not submitted by this server, in the price history.
+pub async fn sync(ctx: RouterCtx) {
+    let mut interval =
+        tokio::time::interval(Duration::from_secs(ctx.conf.prices.sync_interval_secs));
+    loop {
+        interval.tick().await;
+        let amm_cn = ctx.lock_app().await.amm_cn.clone();
+        let amm: AmmState = match ctx.indexer.fetch_current_state(&amm_cn).await {
+            Ok(amm) => amm,
+            Err(e) => {
+                warn!("Failed to fetch AMM state: {e:#}");
+                continue;
+            }
+        };
+        let pools = match pools::reserves(&amm) {
+            Ok(pools) => pools,
+            Err(e) => {
+                error!("Failed to read AMM reserves: {e:#}");
+                continue;
+            }
+        };
+        let recorded = ctx.prices.lock().unwrap().observe(&pools);
+        if recorded {
+            if let Err(e) = save(&ctx.prices).await {
+                error!("Failed to save AMM prices: {e:#}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(timestamp: u64, reserve_a: u128, reserve_b: u128, volume_a: u128) -> PricePoint {
+        PricePoint {
+            timestamp,
+            source: Source::Swap,
+            price: reserve_b as f64 / reserve_a as f64,
+            reserve_a,
+            reserve_b,
+            volume_a,
+            volume_b: volume_a * 2,
+        }
+    }
+
+    fn store(points: Vec<PricePoint>) -> PriceStore {
+        PriceStore {
+            pairs: BTreeMap::from([("a/b".to_string(), points)]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn candles_aggregate_the_points_of_each_interval() {
+        let store = store(vec![
+            point(100, 100, 200, 1),
+            point(130, 100, 400, 2),
+            point(150, 100, 100, 3),
+            point(250, 100, 300, 4),
+        ]);
+
+        let candles = store.candles("a", "b", 60, 0, 1000);
+        let summary: Vec<_> = candles
+            .iter()
+            .map(|c| (c.start, c.open, c.high, c.low, c.close, c.volume_a))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (60, 2.0, 2.0, 2.0, 2.0, 1),
+                (120, 4.0, 4.0, 1.0, 1.0, 5),
+                (240, 3.0, 3.0, 3.0, 3.0, 4),
+            ]
+        );
+        assert_eq!(candles[1].volume_b, 10);
+    }
+
+    #[test]
+    fn candles_are_oriented_and_bounded_as_asked() {
+        let store = store(vec![
+            point(100, 100, 200, 1),
+            point(130, 100, 400, 2),
+            point(200, 100, 100, 3),
+        ]);
+
+        let candles = store.candles("b", "a", 60, 120, 200);
+        assert_eq!(candles.len(), 1);
+        assert_eq!((candles[0].open, candles[0].close), (0.25, 0.25));
+        assert_eq!((candles[0].volume_a, candles[0].volume_b), (4, 2));
+        assert!(store.candles("a", "c", 60, 0, 1000).is_empty());
+    }
+
+    #[test]
+    fn swaps_are_recorded_at_their_execution_price_and_block_time() {
+        let mut store = store(vec![point(100, 100, 200, 0), point(300, 100, 200, 0)]);
+        store.retention = Duration::from_secs(u64::MAX);
+        let pools = [PoolReserves {
+            token_a: "a".to_string(),
+            token_b: "b".to_string(),
+            reserve_a: 110,
+            reserve_b: 182,
+        }];
+        let swap = crate::history::entry(
+            &sdk::TxHash("0x1".to_string()),
+            &sdk::Identity("bob".to_string()),
+            &hyleoof_api::Operation::Swap {
+                token_a: "b".into(),
+                token_b: "a".into(),
+                amount: 18,
+            },
+            Some(10),
+        );
+
+        assert!(store.record_swaps(&pools, &[(200, swap)]));
+        let points = &store.pairs["a/b"];
+        assert_eq!(
+            points.iter().map(|p| p.timestamp).collect::<Vec<_>>(),
+            [100, 200, 300]
+        );
+        // 18 b were paid for 10 a
+        assert!((points[1].price - 1.8).abs() < 1e-9);
+        assert_eq!((points[1].volume_a, points[1].volume_b), (10, 18));
+        assert_eq!((points[1].reserve_a, points[1].reserve_b), (110, 182));
+    }
+}
@@ -0,0 +1,108 @@
+use std::time::{Duration, SystemTime};
+
+use amm::AmmState;
+use anyhow::Result;
+use client_sdk::transaction_builder::TxExecutorBuilder;
+use hyleoof_api::{HistoryEntry, HistoryOperation};
+use sdk::api::TransactionStatus;
+use tokio::time::MissedTickBehavior;
+use tracing::{error, info, warn};
+
+use crate::{fetch_states, pools, prices, RouterCtx};
+
+/// Periodically replaces the local states with the ones of the indexer. The local states run
+/// ahead of the indexer with the transactions this server sent, and miss the ones sent by
+/// others: they are only replaced once every transaction sent from here has settled.
+pub async fn sync_states(ctx: RouterCtx) {
+    let mut interval =
+        tokio::time::interval(Duration::from_secs(ctx.conf.state_sync.interval_secs));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        if let Err(e) = resync(&ctx).await {
+            warn!("Failed to resync the local states: {e:#}");
+        }
+    }
+}
+
+async fn resync(ctx: &RouterCtx) -> Result<()> {
+    let unsettled = ctx.lock_app().await.unsettled.lock().unwrap().clone();
+
+    let mut settled = vec![];
+    let mut swaps = vec![];
+    for tx_hash in unsettled {
+        if let Ok(tx) = ctx.indexer.get_transaction_with_hash(&tx_hash).await {
+            if matches!(
+                tx.transaction_status,
+                TransactionStatus::Success
+                    | TransactionStatus::Failure
+                    | TransactionStatus::TimedOut
+            ) {
+                if tx.transaction_status == TransactionStatus::Success {
+                    let entries = ctx.history.lock().unwrap().transaction(&tx_hash);
+                    if entries
+                        .iter()
+                        .any(|entry| entry.operation == HistoryOperation::Swap)
+                    {
+                        match ctx.indexer.get_block_by_hash(&tx.block_hash).await {
+                            Ok(block) => {
+                                let timestamp = u64::try_from(block.timestamp).unwrap_or_default();
+                                swaps.extend(entries.into_iter().map(|entry| (timestamp, entry)));
+                            }
+                            Err(e) => warn!("Failed to fetch the block of swap {tx_hash}: {e:#}"),
+                        }
+                    }
+                }
+                if let Err(e) = ctx
+                    .history
+                    .lock()
+                    .unwrap()
+                    .update_status(tx_hash.clone(), tx.transaction_status)
+                {
+                    error!("Failed to update transaction history: {e:#}");
+                }
+                settled.push(tx_hash);
+            }
+        }
+    }
+    let states = fetch_states(&ctx.indexer).await?;
+    record_swaps(ctx, &swaps, &states.amm).await;
+
+    let mut app = ctx.lock_app().await;
+    app.settled_amm = states.amm.clone();
+    // Transactions sent while the states were fetched are not in them either.
+    let mut unsettled = app.unsettled.lock().unwrap();
+    unsettled.retain(|tx_hash| !settled.contains(tx_hash));
+    if !unsettled.is_empty() {
+        info!(
+            "{} transactions not settled yet, keeping the local states",
+            unsettled.len()
+        );
+        return Ok(());
+    }
+    drop(unsettled);
+    app.executor = TxExecutorBuilder::new(states).build();
+    app.synced_at = SystemTime::now();
+    app.record_state_metrics();
+    Ok(())
+}
+
+/// Records the settled `swaps`, stamped with the time of their block, along with the reserves
+/// of the `amm` state they settled in.
+async fn record_swaps(ctx: &RouterCtx, swaps: &[(u64, HistoryEntry)], amm: &AmmState) {
+    if swaps.is_empty() {
+        return;
+    }
+    let pools = match pools::reserves(amm) {
+        Ok(pools) => pools,
+        Err(e) => {
+            error!("Failed to read AMM reserves: {e:#}");
+            return;
+        }
+    };
+    if ctx.prices.lock().unwrap().record_swaps(&pools, swaps) {
+        if let Err(e) = prices::save(&ctx.prices).await {
+            error!("Failed to save swap prices: {e:#}");
+        }
+    }
+}
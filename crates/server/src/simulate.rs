@@ -0,0 +1,239 @@
+use std::collections::BTreeSet;
+
+use axum::http::StatusCode;
+use client_sdk::transaction_builder::TxExecutorBuilder;
+use hyleoof_api::{
+    AllowanceDelta, BalanceDelta, Operation, PoolDelta, SimulateRequest, SimulateResponse,
+    TokenDelta,
+};
+use hyllar::HyllarTokenContract;
+use sdk::{erc20::ERC20, ContractName};
+
+use crate::{pools, preflight::Preflight, utils::AppError, HyleOofCtx, States, TOKENS};
+
+/// Builds the transaction of `request` and processes it on a copy of the local states, then
+/// compares the states touched by the operations.
+pub fn simulate(
+    app: &mut HyleOofCtx,
+    request: &SimulateRequest,
+) -> Result<SimulateResponse, AppError> {
+    let mut preflight = Preflight::new(app);
+    let mut amounts_out = vec![];
+    for (i, operation) in request.operations.iter().enumerate() {
+        preflight.operation(i);
+        amounts_out.push(preflight.check(&request.username, operation));
+    }
+    preflight.finish()?;
+
+    let transaction = app.build_transaction(
+        request.username.clone(),
+        request.password.clone(),
+        &request.operations,
+        &amounts_out,
+    )?;
+
+    let before: States = (*app.executor).clone();
+    let mut executor = TxExecutorBuilder::new(before.clone()).build();
+    executor
+        .process(transaction)
+        .map_err(|e| AppError(StatusCode::UNPROCESSABLE_ENTITY, e))?;
+
+    Ok(SimulateResponse {
+        tokens: token_deltas(app, request, &before, &executor),
+        pools: pool_deltas(&before, &executor)?,
+        registered: if !app.identities.is_registered(&before, &request.username.0)
+            && app.identities.is_registered(&executor, &request.username.0)
+        {
+            vec![request.username.clone()]
+        } else {
+            vec![]
+        },
+    })
+}
+
+/// Balances of the accounts the operations involve, and allowances they may change.
+fn token_deltas(
+    app: &HyleOofCtx,
+    request: &SimulateRequest,
+    before: &States,
+    after: &States,
+) -> Vec<TokenDelta> {
+    let identity = request.username.0.clone();
+    let amm = app.amm_cn.0.clone();
+
+    let mut accounts = BTreeSet::from([identity.clone()]);
+    // (owner, spender) pairs
+    let mut allowance_pairs = BTreeSet::new();
+    for operation in &request.operations {
+        match operation {
+            Operation::Transfer { recipient, .. } => {
+                accounts.insert(recipient.clone());
+            }
+            Operation::Approve { spender, .. } => {
+                allowance_pairs.insert((identity.clone(), spender.clone()));
+            }
+            Operation::TransferFrom {
+                owner, recipient, ..
+            } => {
+                accounts.insert(owner.clone());
+                accounts.insert(recipient.clone());
+                allowance_pairs.insert((owner.clone(), identity.clone()));
+            }
+            Operation::Swap { .. } => {
+                accounts.insert(amm.clone());
+                allowance_pairs.insert((identity.clone(), amm.clone()));
+            }
+            Operation::Register => {}
+        }
+    }
+
+    let mut deltas = vec![];
+    for token in TOKENS.map(ContractName::from) {
+        let (Some(token_before), Some(token_after)) = (before.token(&token), after.token(&token))
+        else {
+            continue;
+        };
+        let caller = request.username.clone();
+        let token_before = HyllarTokenContract::init(token_before.clone(), caller.clone());
+        let token_after = HyllarTokenContract::init(token_after.clone(), caller);
+
+        let balances: Vec<_> = accounts
+            .iter()
+            .map(|account| BalanceDelta {
+                account: account.clone(),
+                before: token_before.balance_of(account).unwrap_or(0),
+                after: token_after.balance_of(account).unwrap_or(0),
+            })
+            .filter(|delta| delta.before != delta.after)
+            .collect();
+        let allowances: Vec<_> = allowance_pairs
+            .iter()
+            .map(|(owner, spender)| AllowanceDelta {
+                owner: owner.clone(),
+                spender: spender.clone(),
+                before: token_before.allowance(owner, spender).unwrap_or(0),
+                after: token_after.allowance(owner, spender).unwrap_or(0),
+            })
+            .filter(|delta| delta.before != delta.after)
+            .collect();
+
+        if !balances.is_empty() || !allowances.is_empty() {
+            deltas.push(TokenDelta {
+                token,
+                balances,
+                allowances,
+            });
+        }
+    }
+    deltas
+}
+
+fn pool_deltas(before: &States, after: &States) -> Result<Vec<PoolDelta>, AppError> {
+    let before = pools::reserves(&before.amm)?;
+    Ok(pools::reserves(&after.amm)?
+        .into_iter()
+        .filter_map(|pool| {
+            let previous = before
+                .iter()
+                .find(|p| p.token_a == pool.token_a && p.token_b == pool.token_b);
+            let reserves_before = previous.map_or((0, 0), |p| (p.reserve_a, p.reserve_b));
+            let reserves_after = (pool.reserve_a, pool.reserve_b);
+            (reserves_before != reserves_after).then_some(PoolDelta {
+                token_a: pool.token_a,
+                token_b: pool.token_b,
+                reserves_before,
+                reserves_after,
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(username: &str, operations: Vec<Operation>) -> SimulateRequest {
+        SimulateRequest {
+            username: username.into(),
+            password: "password".to_string(),
+            operations,
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_the_changes_without_applying_them() {
+        let mut app = HyleOofCtx::for_tests();
+        let faucet = app.faucet.0.clone();
+        app.register_for_tests(&faucet);
+        let request = request(
+            &faucet,
+            vec![
+                Operation::Transfer {
+                    recipient: "bob.hydentity".to_string(),
+                    token: "hyllar".into(),
+                    amount: 100,
+                },
+                Operation::Approve {
+                    spender: "amm".to_string(),
+                    token: "hyllar".into(),
+                    amount: 1000,
+                },
+                Operation::Swap {
+                    token_a: "hyllar".into(),
+                    token_b: "hyllar2".into(),
+                    amount: 1000,
+                },
+            ],
+        );
+
+        let response = simulate(&mut app, &request).unwrap();
+        assert!(response.registered.is_empty());
+        let hyllar = response
+            .tokens
+            .iter()
+            .find(|delta| delta.token.0 == "hyllar")
+            .unwrap();
+        let bob = hyllar
+            .balances
+            .iter()
+            .find(|delta| delta.account == "bob.hydentity")
+            .unwrap();
+        assert_eq!((bob.before, bob.after), (0, 100));
+        // Approved then spent by the swap
+        assert!(hyllar.allowances.is_empty());
+        assert_eq!(response.pools.len(), 1);
+        let pool = &response.pools[0];
+        assert_ne!(pool.reserves_before, pool.reserves_after);
+
+        let hyllar = HyllarTokenContract::init(
+            app.token_state(&"hyllar".into()).unwrap().clone(),
+            faucet.clone().into(),
+        );
+        assert_eq!(hyllar.balance_of("bob.hydentity").unwrap_or(0), 0);
+    }
+
+    #[tokio::test]
+    async fn lists_the_identities_it_registers() {
+        let mut app = HyleOofCtx::for_tests();
+        let response = simulate(
+            &mut app,
+            &request("alice.hydentity", vec![Operation::Register]),
+        )
+        .unwrap();
+        assert_eq!(response.registered, vec!["alice.hydentity".into()]);
+        assert!(!app.is_registered("alice.hydentity"));
+    }
+
+    #[tokio::test]
+    async fn failing_operations_are_unprocessable() {
+        let mut app = HyleOofCtx::for_tests();
+        app.register_for_tests("bob.hydentity");
+        let transfer = Operation::Transfer {
+            recipient: "alice.hydentity".to_string(),
+            token: "hyllar".into(),
+            amount: 1,
+        };
+        let error = simulate(&mut app, &request("bob.hydentity", vec![transfer])).unwrap_err();
+        assert_eq!(error.0, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+}
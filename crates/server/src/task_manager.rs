@@ -1,40 +1,147 @@
-use std::sync::Arc;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex as SyncMutex},
+    time::{Duration, Instant},
+};
 
 use client_sdk::{rest_client::NodeApiHttpClient, transaction_builder::ProofTxBuilder};
-use tokio::sync::{mpsc, Mutex};
-use tracing::error;
+use sdk::TxHash;
+use tokio::{
+    sync::{mpsc, Mutex},
+    task::JoinHandle,
+};
+use tracing::{error, info, Instrument, Span};
+
+use crate::metrics;
+
+type Job = (TxHash, ProofTxBuilder, Span);
 
 pub struct Prover {
-    sender: mpsc::UnboundedSender<ProofTxBuilder>,
+    /// Transactions to prove, with the span of the request that submitted them. Taken on
+    /// shutdown so that the worker stops once the queue is drained.
+    sender: SyncMutex<Option<mpsc::UnboundedSender<Job>>>,
+    queue: Arc<SyncMutex<Queue>>,
+    worker: SyncMutex<Option<JoinHandle<()>>>,
+}
+
+/// Transactions accepted by the prover and not fully proven yet.
+#[derive(Default)]
+struct Queue {
+    /// Waiting in the channel, oldest first, with the time they were queued.
+    waiting: VecDeque<(TxHash, Instant)>,
+    /// Being proven by the worker.
+    proving: Option<TxHash>,
 }
 
 impl Prover {
     pub fn new(node_client: Arc<NodeApiHttpClient>) -> Self {
-        let (sender, receiver) = mpsc::unbounded_channel::<ProofTxBuilder>();
+        let (sender, receiver) = mpsc::unbounded_channel::<Job>();
         let receiver = Arc::new(Mutex::new(receiver));
+        let queue = Arc::new(SyncMutex::new(Queue::default()));
 
-        tokio::spawn(async move {
-            while let Some(tx) = receiver.lock().await.recv().await {
-                for proof in tx.iter_prove() {
-                    match proof.await {
-                        Ok(proof) => {
-                            node_client.send_tx_proof(&proof).await.unwrap();
-                        }
-                        Err(e) => {
-                            error!("failed to prove transaction: {e}");
-                            continue;
-                        }
-                    };
+        let worker_queue = queue.clone();
+        let worker = tokio::spawn(async move {
+            while let Some((tx_hash, tx, span)) = receiver.lock().await.recv().await {
+                metrics::PROVING_QUEUE_DEPTH.dec();
+                {
+                    let mut queue = worker_queue.lock().unwrap();
+                    queue.waiting.pop_front();
+                    queue.proving = Some(tx_hash);
                 }
+                async {
+                    for proof in tx.iter_prove() {
+                        let started = Instant::now();
+                        let proof = proof.await;
+                        metrics::PROOF_DURATION.observe(started.elapsed().as_secs_f64());
+                        match proof {
+                            Ok(proof) => match node_client.send_tx_proof(&proof).await {
+                                Ok(_) => info!("Proof transaction submitted"),
+                                Err(e) => {
+                                    metrics::PROOF_FAILURES.inc();
+                                    error!("failed to send proof: {e}");
+                                }
+                            },
+                            Err(e) => {
+                                metrics::PROOF_FAILURES.inc();
+                                error!("failed to prove transaction: {e}");
+                                continue;
+                            }
+                        };
+                    }
+                }
+                .instrument(span)
+                .await;
+                worker_queue.lock().unwrap().proving = None;
             }
         });
 
-        Prover { sender }
+        Prover {
+            sender: SyncMutex::new(Some(sender)),
+            queue,
+            worker: SyncMutex::new(Some(worker)),
+        }
+    }
+
+    pub async fn add(&self, tx_hash: TxHash, tx: ProofTxBuilder) {
+        let sender = self.sender.lock().unwrap();
+        let Some(sender) = sender.as_ref() else {
+            error!("Prover is shutting down, transaction {tx_hash} will not be proven");
+            return;
+        };
+        metrics::PROVING_QUEUE_DEPTH.inc();
+        self.queue
+            .lock()
+            .unwrap()
+            .waiting
+            .push_back((tx_hash.clone(), Instant::now()));
+        if let Err(e) = sender.send((tx_hash, tx, Span::current())) {
+            metrics::PROVING_QUEUE_DEPTH.dec();
+            self.queue.lock().unwrap().waiting.pop_back();
+            error!("Failed to add transaction: {e}");
+        }
+    }
+
+    /// False once the worker task has stopped, e.g. after a panic while proving.
+    pub fn is_alive(&self) -> bool {
+        self.worker
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|worker| !worker.is_finished())
+    }
+
+    /// How long the oldest transaction not picked by the worker yet has been waiting.
+    pub fn oldest_queued(&self) -> Option<Duration> {
+        self.queue
+            .lock()
+            .unwrap()
+            .waiting
+            .front()
+            .map(|(_, queued_at)| queued_at.elapsed())
     }
 
-    pub async fn add(&self, tx: ProofTxBuilder) {
-        if let Err(e) = self.sender.send(tx) {
-            eprintln!("Failed to add transaction: {}", e);
+    /// Stops accepting transactions and lets the worker prove the queued ones for at most
+    /// `deadline`. Returns the transactions left unproven.
+    pub async fn shutdown(&self, deadline: Duration) -> Vec<TxHash> {
+        self.sender.lock().unwrap().take();
+        let Some(mut worker) = self.worker.lock().unwrap().take() else {
+            return vec![];
+        };
+
+        let pending = self.queue.lock().unwrap().waiting.len();
+        if pending > 0 {
+            info!("Waiting up to {deadline:?} for {pending} queued transactions to be proven");
         }
+        if tokio::time::timeout(deadline, &mut worker).await.is_err() {
+            worker.abort();
+        }
+
+        let queue = self.queue.lock().unwrap();
+        queue
+            .proving
+            .iter()
+            .cloned()
+            .chain(queue.waiting.iter().map(|(tx_hash, _)| tx_hash.clone()))
+            .collect()
     }
 }
@@ -0,0 +1,407 @@
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::{Arc, Mutex as SyncMutex},
+    time::Duration,
+};
+
+use amm::AmmState;
+use anyhow::{bail, Context, Result};
+use client_sdk::rest_client::IndexerApiHttpClient;
+use hyleoof_api::TwapResponse;
+use sdk::{api::APIContractState, BlockHeight, ContractName, StateDigest};
+use serde::{Deserialize, Serialize};
+use tracing::{error, warn};
+
+use crate::{
+    pools::{self, PoolReserves},
+    prices::pair_key,
+    store::{load_json, save_json},
+    RouterCtx,
+};
+
+/// Times a block is tried before it is skipped.
+const MAX_BLOCK_ATTEMPTS: u32 = 5;
+
+/// Cumulative prices of a pair at a block.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Observation {
+    block_height: u64,
+    /// Block timestamp, in seconds.
+    timestamp: u64,
+    /// Sum of the price of `token_a` in `token_b` times the seconds it held, since the first
+    /// observation.
+    cumulative_a: f64,
+    cumulative_b: f64,
+}
+
+/// Price accumulators of an unordered pair, oriented with its tokens in alphabetical order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Accumulator {
+    observations: Vec<Observation>,
+    /// Reserves at the last observed block, which hold until the next one.
+    reserve_a: u128,
+    reserve_b: u128,
+}
+
+/// Time-weighted average prices of the AMM pairs, computed from the reserves observed at each
+/// block. The reserves seen at a block are only accounted for from the next one on, so that a
+/// price moved within a single block weighs no more than the time it actually held.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Oracle {
+    #[serde(skip)]
+    path: PathBuf,
+    /// Longest window served, older observations are dropped.
+    #[serde(skip)]
+    max_window: u64,
+    /// Height of the last block observed, whether or not it held any pair.
+    #[serde(default)]
+    last_block: Option<u64>,
+    pairs: BTreeMap<String, Accumulator>,
+    /// Held while writing the oracle, so that snapshots reach the disk in order.
+    #[serde(skip)]
+    saving: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl Oracle {
+    pub fn open(path: PathBuf, max_window: u64) -> Result<Self> {
+        let mut oracle: Oracle = load_json(&path)?;
+        oracle.path = path;
+        oracle.max_window = max_window;
+        Ok(oracle)
+    }
+
+    pub fn last_block(&self) -> Option<u64> {
+        self.last_block
+    }
+
+    /// Accumulates the prices of the reserves observed at the previous block over the time
+    /// elapsed since, then keeps `pools`, the reserves at the end of this block, from this block
+    /// on. Blocks already observed are ignored. Nothing is written until [`save`].
+    pub fn observe(&mut self, block_height: u64, timestamp: u64, pools: &[PoolReserves]) {
+        if self
+            .last_block
+            .is_some_and(|last_block| block_height <= last_block)
+        {
+            return;
+        }
+        self.last_block = Some(block_height);
+        for pool in pools {
+            let (key, flipped) = pair_key(&pool.token_a, &pool.token_b);
+            let (reserve_a, reserve_b) = if flipped {
+                (pool.reserve_b, pool.reserve_a)
+            } else {
+                (pool.reserve_a, pool.reserve_b)
+            };
+
+            let Some(accumulator) = self.pairs.get_mut(&key) else {
+                self.pairs.insert(
+                    key,
+                    Accumulator {
+                        observations: vec![Observation {
+                            block_height,
+                            timestamp,
+                            cumulative_a: 0.0,
+                            cumulative_b: 0.0,
+                        }],
+                        reserve_a,
+                        reserve_b,
+                    },
+                );
+                continue;
+            };
+            let last = accumulator
+                .observations
+                .last()
+                .expect("accumulators have at least one observation");
+            if block_height <= last.block_height || timestamp < last.timestamp {
+                continue;
+            }
+
+            let elapsed = (timestamp - last.timestamp) as f64;
+            let (price_a, price_b) = if accumulator.reserve_a == 0 || accumulator.reserve_b == 0 {
+                (0.0, 0.0)
+            } else {
+                (
+                    accumulator.reserve_b as f64 / accumulator.reserve_a as f64,
+                    accumulator.reserve_a as f64 / accumulator.reserve_b as f64,
+                )
+            };
+            let observation = Observation {
+                block_height,
+                timestamp,
+                cumulative_a: last.cumulative_a + price_a * elapsed,
+                cumulative_b: last.cumulative_b + price_b * elapsed,
+            };
+            accumulator.observations.push(observation);
+            accumulator.reserve_a = reserve_a;
+            accumulator.reserve_b = reserve_b;
+
+            // Keep the newest observation older than the longest window, to start it from.
+            let oldest = timestamp.saturating_sub(self.max_window);
+            let expired = accumulator
+                .observations
+                .iter()
+                .take_while(|observation| observation.timestamp < oldest)
+                .count();
+            accumulator.observations.drain(..expired.saturating_sub(1));
+        }
+    }
+
+    /// Gives up on block `block_height`, whose reserves are left out: the ones of the previous
+    /// block are accounted for until the next one observed.
+    pub fn skip(&mut self, block_height: u64) {
+        if self
+            .last_block
+            .is_none_or(|last_block| block_height > last_block)
+        {
+            self.last_block = Some(block_height);
+        }
+    }
+
+    /// Average prices of `token_a` in `token_b` and back over the last `window` seconds of
+    /// observed blocks. It starts from the newest observation at least `window` old, the
+    /// oldest one when there is none yet.
+    pub fn twap(&self, token_a: &str, token_b: &str, window: u64) -> Result<TwapResponse> {
+        let (key, flipped) = pair_key(token_a, token_b);
+        let Some(accumulator) = self.pairs.get(&key) else {
+            bail!("no observation of {token_a}/{token_b} yet");
+        };
+        let observations = &accumulator.observations;
+        let end = observations
+            .last()
+            .expect("accumulators have at least one observation");
+        let start = observations
+            .iter()
+            .rev()
+            .find(|observation| observation.timestamp + window <= end.timestamp)
+            .unwrap_or(&observations[0]);
+        if start.timestamp == end.timestamp {
+            bail!("{token_a}/{token_b} was observed over a single block only");
+        }
+
+        let elapsed = (end.timestamp - start.timestamp) as f64;
+        let price_a = (end.cumulative_a - start.cumulative_a) / elapsed;
+        let price_b = (end.cumulative_b - start.cumulative_b) / elapsed;
+        let (price_a_in_b, price_b_in_a) = if flipped {
+            (price_b, price_a)
+        } else {
+            (price_a, price_b)
+        };
+        Ok(TwapResponse {
+            token_a: token_a.to_string(),
+            token_b: token_b.to_string(),
+            window: end.timestamp - start.timestamp,
+            from_block: start.block_height,
+            to_block: end.block_height,
+            price_a_in_b,
+            price_b_in_a,
+        })
+    }
+}
+
+/// AMM state of the indexer at the end of block `height`, decoded from its digest as
+/// `fetch_current_state` does for the current one.
+async fn amm_state_at(
+    indexer: &IndexerApiHttpClient,
+    amm_cn: &ContractName,
+    height: u64,
+) -> Result<AmmState> {
+    let url = indexer
+        .url
+        .join(&format!("v1/indexer/contract/{amm_cn}/state/{height}"))?;
+    let state: APIContractState = indexer
+        .reqwest_client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    StateDigest(state.state).try_into()
+}
+
+/// Observes every block the indexer has since the last observed one, each with the AMM state at
+/// its height, so that no reserves are missed between two syncs. The first sync starts from the
+/// last block.
+pub async fn sync(ctx: RouterCtx) {
+    let mut interval = tokio::time::interval(Duration::from_secs(ctx.conf.twap.sync_interval_secs));
+    let mut failures = None;
+    loop {
+        interval.tick().await;
+        if let Err(e) = observe_blocks(&ctx, &mut failures).await {
+            warn!("Failed to update TWAP accumulators: {e:#}");
+        }
+    }
+}
+
+/// Writes `oracle` to disk from a blocking task, without holding its lock during the write.
+async fn save(oracle: &SyncMutex<Oracle>) -> Result<()> {
+    let saving = oracle.lock().unwrap().saving.clone();
+    let _saving = saving.lock().await;
+    let (path, snapshot) = {
+        let oracle = oracle.lock().unwrap();
+        (oracle.path.clone(), serde_json::to_value(&*oracle)?)
+    };
+    tokio::task::spawn_blocking(move || save_json(&path, &snapshot)).await?
+}
+
+/// Observes the blocks from the one after the last observed on. A block that cannot be read is
+/// retried by the next syncs, and skipped after [`MAX_BLOCK_ATTEMPTS`]: `failures` holds the
+/// height that failed last and how many times.
+async fn observe_blocks(ctx: &RouterCtx, failures: &mut Option<(u64, u32)>) -> Result<()> {
+    let amm_cn = ctx.lock_app().await.amm_cn.clone();
+    let last_block = ctx
+        .indexer
+        .get_last_block()
+        .await
+        .context("fetching last block")?
+        .height;
+    let first = match ctx.oracle.lock().unwrap().last_block() {
+        Some(observed) => observed + 1,
+        None => last_block,
+    };
+    let last = last_block.min(first.saturating_add(ctx.conf.twap.max_blocks_per_sync) - 1);
+
+    let mut result = Ok(());
+    for height in first..=last {
+        let observed = async {
+            let block = ctx
+                .indexer
+                .get_block_by_height(&BlockHeight(height))
+                .await
+                .with_context(|| format!("fetching block {height}"))?;
+            let amm = amm_state_at(&ctx.indexer, &amm_cn, height)
+                .await
+                .with_context(|| format!("fetching AMM state at block {height}"))?;
+            let pools = pools::reserves(&amm)?;
+            anyhow::Ok((block, pools))
+        }
+        .await;
+        match observed {
+            Ok((block, pools)) => {
+                let timestamp = u64::try_from(block.timestamp).unwrap_or_default();
+                ctx.oracle
+                    .lock()
+                    .unwrap()
+                    .observe(height, timestamp, &pools);
+            }
+            Err(e) => {
+                let attempts = match *failures {
+                    Some((failed, attempts)) if failed == height => attempts + 1,
+                    _ => 1,
+                };
+                if attempts < MAX_BLOCK_ATTEMPTS {
+                    // Retried from this block on at the next sync
+                    *failures = Some((height, attempts));
+                    result = Err(e);
+                    break;
+                }
+                error!("Skipping block {height} after {attempts} attempts: {e:#}");
+                ctx.oracle.lock().unwrap().skip(height);
+            }
+        }
+        *failures = None;
+    }
+    if first <= last {
+        if let Err(e) = save(&ctx.oracle).await {
+            error!("Failed to save TWAP accumulators: {e:#}");
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(reserve_a: u128, reserve_b: u128) -> Vec<PoolReserves> {
+        vec![PoolReserves {
+            token_a: "a".to_string(),
+            token_b: "b".to_string(),
+            reserve_a,
+            reserve_b,
+        }]
+    }
+
+    fn oracle(max_window: u64) -> Oracle {
+        Oracle {
+            max_window,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn prices_are_weighted_by_the_time_they_held() {
+        let mut oracle = oracle(1000);
+        oracle.observe(1, 0, &pool(100, 100));
+        oracle.observe(2, 30, &pool(100, 400));
+        oracle.observe(3, 40, &pool(100, 400));
+
+        // 1.0 for 30s then 4.0 for 10s
+        let twap = oracle.twap("a", "b", 1000).unwrap();
+        assert_eq!((twap.from_block, twap.to_block, twap.window), (1, 3, 40));
+        assert_eq!(twap.price_a_in_b, 1.75);
+        let twap = oracle.twap("b", "a", 1000).unwrap();
+        assert_eq!(twap.price_a_in_b, (30.0 + 0.25 * 10.0) / 40.0);
+    }
+
+    #[test]
+    fn reserves_moved_within_a_block_count_from_the_next_one() {
+        let mut oracle = oracle(1000);
+        oracle.observe(1, 0, &pool(100, 100));
+        oracle.observe(2, 10, &pool(1, 1_000_000));
+        assert_eq!(oracle.twap("a", "b", 1000).unwrap().price_a_in_b, 1.0);
+    }
+
+    #[test]
+    fn blocks_already_observed_are_ignored() {
+        let mut oracle = oracle(1000);
+        oracle.observe(1, 0, &pool(100, 100));
+        oracle.observe(2, 10, &pool(100, 200));
+        oracle.observe(2, 10, &pool(100, 900));
+        oracle.observe(1, 20, &pool(100, 900));
+        assert_eq!(oracle.last_block(), Some(2));
+
+        oracle.observe(3, 20, &pool(100, 200));
+        assert_eq!(oracle.twap("a", "b", 1000).unwrap().price_a_in_b, 1.5);
+    }
+
+    #[test]
+    fn windows_start_from_the_newest_old_enough_observation() {
+        let mut oracle = oracle(15);
+        oracle.observe(1, 0, &pool(100, 100));
+        oracle.observe(2, 10, &pool(100, 200));
+        oracle.observe(3, 20, &pool(100, 300));
+        oracle.observe(4, 30, &pool(100, 300));
+
+        let twap = oracle.twap("a", "b", 10).unwrap();
+        assert_eq!((twap.from_block, twap.price_a_in_b), (3, 3.0));
+        // Observations older than the longest window are dropped, but the one starting it.
+        let twap = oracle.twap("a", "b", 1000).unwrap();
+        assert_eq!((twap.from_block, twap.price_a_in_b), (2, 2.5));
+    }
+
+    #[test]
+    fn a_single_block_gives_no_average() {
+        let mut oracle = oracle(1000);
+        assert!(oracle.twap("a", "b", 10).is_err());
+        oracle.observe(1, 0, &pool(100, 100));
+        assert!(oracle.twap("a", "b", 10).is_err());
+    }
+
+    #[test]
+    fn skipped_blocks_are_not_observed_again() {
+        let mut oracle = oracle(1000);
+        oracle.observe(1, 0, &pool(100, 100));
+        oracle.skip(2);
+        oracle.observe(2, 10, &pool(100, 900));
+        oracle.observe(3, 20, &pool(100, 400));
+
+        assert_eq!(oracle.last_block(), Some(3));
+        // 1.0 from block 1 until block 3, block 2 is left out
+        let twap = oracle.twap("a", "b", 1000).unwrap();
+        assert_eq!((twap.from_block, twap.to_block), (1, 3));
+        assert_eq!(twap.price_a_in_b, 1.0);
+    }
+}
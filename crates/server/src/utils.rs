@@ -1,7 +1,9 @@
 use axum::{
     http::StatusCode,
     response::{IntoResponse, Response},
+    Json,
 };
+use hyleoof_api::ValidationErrors;
 
 // Make our own error that wraps `anyhow::Error`.
 pub struct AppError(pub StatusCode, pub anyhow::Error);
@@ -9,6 +11,10 @@ pub struct AppError(pub StatusCode, pub anyhow::Error);
 // Tell axum how to convert `AppError` into a response.
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
+        if let Some(errors) = self.1.downcast_ref::<ValidationErrors>() {
+            tracing::warn!("{errors}");
+            return (self.0, Json(errors.clone())).into_response();
+        }
         tracing::error!("{}", self.1);
         (self.0, format!("{}", self.1)).into_response()
     }
@@ -0,0 +1,343 @@
+use axum::http::StatusCode;
+use hyleoof_api::{
+    ApproveRequest, BatchRequest, FaucetRequest, FieldError, Operation, QuoteQuery,
+    RegisterRequest, SimulateRequest, SwapRequest, TransferFromRequest, TransferRequest,
+    ValidationErrors,
+};
+use hyllar::HyllarToken;
+use sdk::ContractName;
+
+use crate::{utils::AppError, HyleOofCtx, RouterCtx};
+
+/// Checks a request against the deployed contracts and the local states, before any
+/// transaction is built.
+pub trait Validate {
+    fn validate(&self, v: &mut Validator);
+}
+
+/// Collects the field errors of a request.
+pub struct Validator<'a> {
+    app: &'a HyleOofCtx,
+    errors: Vec<FieldError>,
+    /// Errors caused by a conflict with the on-chain state. The request gets a 409 when they
+    /// are its only errors, a 400 otherwise.
+    conflicts: usize,
+}
+
+impl<'a> Validator<'a> {
+    pub fn new(app: &'a HyleOofCtx) -> Self {
+        Validator {
+            app,
+            errors: vec![],
+            conflicts: 0,
+        }
+    }
+
+    pub fn error(&mut self, field: &str, message: impl Into<String>) {
+        self.errors.push(FieldError {
+            field: field.to_string(),
+            message: message.into(),
+        });
+    }
+
+    fn has_error(&self, field: &str) -> bool {
+        self.errors.iter().any(|error| error.field == field)
+    }
+
+    pub fn amount(&mut self, field: &str, amount: u128) {
+        if amount == 0 {
+            self.error(field, "must be greater than 0");
+        }
+    }
+
+    /// `token` is a manifest token the server holds the state of.
+    pub fn token(&mut self, field: &str, token: &ContractName) -> Option<&'a HyllarToken> {
+        let state = self.app.token_state(token);
+        if state.is_none() {
+            self.error(field, format!("unknown token {token}"));
+        }
+        state
+    }
+
+    /// `identity` is an account of one of the identity providers, e.g. `bob.hydentity`.
+    pub fn identity(&mut self, field: &str, identity: &str) {
+        match identity.split_once('.') {
+            Some((name, contract))
+                if !name.is_empty() && self.app.identities.get(contract).is_some() => {}
+            _ => {
+                let contract = self.app.identities.default_provider().contract_name();
+                self.error(field, format!("must be an identity like `bob.{contract}`"));
+            }
+        }
+    }
+
+    /// `identity` is an account registered on-chain.
+    pub fn registered(&mut self, field: &str, identity: &str) {
+        self.identity(field, identity);
+        if !self.has_error(field) && !self.app.is_registered(identity) {
+            self.error(field, format!("identity {identity} is not registered"));
+        }
+    }
+
+    /// `identity` is an account not taken yet, a 409 otherwise.
+    pub fn unregistered(&mut self, field: &str, identity: &str) {
+        self.identity(field, identity);
+        if !self.has_error(field) && self.app.is_registered(identity) {
+            self.error(field, format!("identity {identity} is already registered"));
+            self.conflicts += 1;
+        }
+    }
+
+    /// `account` is either an identity or a manifest contract.
+    pub fn account(&mut self, field: &str, account: &str) {
+        if !self.app.deployment.contains(account) {
+            self.identity(field, account);
+        }
+    }
+
+    /// Operations of a transaction of `username` authorized by its password, which may be a
+    /// single registration.
+    fn batch(&mut self, username: &str, operations: &[Operation]) {
+        let registers = matches!(operations, [Operation::Register]);
+        if registers {
+            self.unregistered("username", username);
+        } else {
+            self.registered("username", username);
+        }
+        self.operations(
+            username,
+            operations,
+            (!registers).then_some("register must be the only operation"),
+        );
+    }
+
+    /// Operations of `username`, `register_error` is reported for registrations when they are
+    /// not allowed.
+    fn operations(
+        &mut self,
+        username: &str,
+        operations: &[Operation],
+        register_error: Option<&str>,
+    ) {
+        if operations.is_empty() {
+            self.error("operations", "must not be empty");
+        }
+
+        for (i, operation) in operations.iter().enumerate() {
+            let field = |name: &str| format!("operations[{i}].{name}");
+            match operation {
+                Operation::Transfer {
+                    recipient,
+                    token,
+                    amount,
+                } => {
+                    self.account(&field("recipient"), recipient);
+                    if recipient == username {
+                        self.error(&field("recipient"), "cannot transfer to yourself");
+                    }
+                    self.token(&field("token"), token);
+                    self.amount(&field("amount"), *amount);
+                }
+                Operation::Approve {
+                    spender,
+                    token,
+                    amount,
+                } => {
+                    self.account(&field("spender"), spender);
+                    self.token(&field("token"), token);
+                    self.amount(&field("amount"), *amount);
+                }
+                Operation::TransferFrom {
+                    owner,
+                    recipient,
+                    token,
+                    amount,
+                } => {
+                    self.account(&field("owner"), owner);
+                    if owner == username {
+                        self.error(&field("owner"), "use a transfer to move your own tokens");
+                    }
+                    self.account(&field("recipient"), recipient);
+                    self.token(&field("token"), token);
+                    self.amount(&field("amount"), *amount);
+                }
+                Operation::Swap {
+                    token_a,
+                    token_b,
+                    amount,
+                } => {
+                    self.token(&field("token_a"), token_a);
+                    self.token(&field("token_b"), token_b);
+                    if token_a == token_b {
+                        self.error(&field("token_b"), "cannot swap a token for itself");
+                    }
+                    self.amount(&field("amount"), *amount);
+                }
+                Operation::Register => {
+                    if let Some(message) = register_error {
+                        self.error(&field("type"), message);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn finish(self) -> Result<(), AppError> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            let status = if self.conflicts == self.errors.len() {
+                StatusCode::CONFLICT
+            } else {
+                StatusCode::BAD_REQUEST
+            };
+            Err(AppError(
+                status,
+                ValidationErrors {
+                    errors: self.errors,
+                }
+                .into(),
+            ))
+        }
+    }
+}
+
+impl RouterCtx {
+    pub async fn validate(&self, request: &impl Validate) -> Result<(), AppError> {
+        let app = self.lock_app().await;
+        let mut validator = Validator::new(&app);
+        request.validate(&mut validator);
+        validator.finish()
+    }
+}
+
+impl Validate for FaucetRequest {
+    fn validate(&self, v: &mut Validator) {
+        v.identity("username", &self.username);
+        v.token("token", &self.token);
+    }
+}
+
+impl Validate for TransferRequest {
+    fn validate(&self, v: &mut Validator) {
+        v.registered("username", &self.username);
+        v.account("recipient", &self.recipient);
+        if self.recipient == self.username {
+            v.error("recipient", "cannot transfer to yourself");
+        }
+        v.token("token", &self.token);
+        v.amount("amount", self.amount);
+    }
+}
+
+impl Validate for TransferFromRequest {
+    fn validate(&self, v: &mut Validator) {
+        v.registered("username", &self.username);
+        v.account("owner", &self.owner);
+        if self.owner == self.username {
+            v.error("owner", "use a transfer to move your own tokens");
+        }
+        v.account("recipient", &self.recipient);
+        v.token("token", &self.token);
+        v.amount("amount", self.amount);
+    }
+}
+
+impl Validate for ApproveRequest {
+    fn validate(&self, v: &mut Validator) {
+        v.registered("username", &self.username);
+        v.account("spender", &self.spender);
+        if self.spender == self.username {
+            v.error("spender", "cannot approve yourself");
+        }
+        v.token("token", &self.token.clone().into());
+        v.amount("amount", self.amount);
+    }
+}
+
+impl Validate for SwapRequest {
+    fn validate(&self, v: &mut Validator) {
+        v.registered("username", &self.username.0);
+        v.token("token_a", &self.token_a);
+        v.token("token_b", &self.token_b);
+        if self.token_a == self.token_b {
+            v.error("token_b", "cannot swap a token for itself");
+        }
+        v.amount("amount", self.amount);
+    }
+}
+
+impl Validate for RegisterRequest {
+    fn validate(&self, v: &mut Validator) {
+        v.unregistered("username", &self.username.0);
+        if self.password.is_empty() {
+            v.error("password", "must not be empty");
+        }
+    }
+}
+
+impl Validate for QuoteQuery {
+    fn validate(&self, v: &mut Validator) {
+        v.token("token_a", &self.token_a);
+        v.token("token_b", &self.token_b);
+        if self.token_a == self.token_b {
+            v.error("token_b", "cannot swap a token for itself");
+        }
+        v.amount("amount", self.amount);
+    }
+}
+
+impl Validate for BatchRequest {
+    fn validate(&self, v: &mut Validator) {
+        v.batch(&self.username.0, &self.operations);
+    }
+}
+
+impl Validate for SimulateRequest {
+    fn validate(&self, v: &mut Validator) {
+        v.batch(&self.username.0, &self.operations);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn taken_names_alone_are_conflicts() {
+        let mut app = HyleOofCtx::for_tests();
+        app.register_for_tests("bob.hydentity");
+
+        let mut v = Validator::new(&app);
+        v.unregistered("username", "bob.hydentity");
+        assert_eq!(v.finish().unwrap_err().0, StatusCode::CONFLICT);
+
+        let mut v = Validator::new(&app);
+        v.unregistered("username", "alice.hydentity");
+        assert!(v.finish().is_ok());
+    }
+
+    #[tokio::test]
+    async fn conflicts_with_other_errors_are_bad_requests() {
+        let mut app = HyleOofCtx::for_tests();
+        app.register_for_tests("bob.hydentity");
+
+        let mut v = Validator::new(&app);
+        v.unregistered("username", "bob.hydentity");
+        v.amount("amount", 0);
+        assert_eq!(v.finish().unwrap_err().0, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn identities_need_a_known_provider() {
+        let app = HyleOofCtx::for_tests();
+
+        let mut v = Validator::new(&app);
+        v.registered("username", "bob");
+        v.registered("recipient", "bob.unknown");
+        v.registered("owner", "alice.hydentity");
+        let fields: Vec<_> = v.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, ["username", "recipient", "owner"]);
+        assert_eq!(v.finish().unwrap_err().0, StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,11 @@
+#!/bin/sh
+# Regenerates src/api/schema.ts from the OpenAPI document of the server.
+set -e
+
+cd "$(dirname "$0")/.."
+openapi=$(mktemp --suffix .json)
+trap 'rm -f "$openapi"' EXIT
+
+cargo run --quiet --manifest-path ../Cargo.toml --bin server -- openapi > "$openapi"
+pnpm dlx openapi-typescript@7.5.2 "$openapi" --root-types=false -o src/api/schema.ts
+pnpm exec prettier --write src/api/schema.ts
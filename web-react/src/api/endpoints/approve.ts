@@ -1,5 +1,6 @@
 import { createApiRequest } from "../createApiRequest";
 import { AuthParams, SERVER_URL } from "../constants";
+import { ApproveRequest, TxHashResponse } from "../types";
 
 interface ApproveParams extends AuthParams {
   spender: string;
@@ -14,16 +15,17 @@ export default async function approve({
   token,
   amount,
 }: ApproveParams) {
-  return createApiRequest({
+  const body: ApproveRequest = {
+    username: username + ".hydentity",
+    password,
+    token,
+    spender,
+    amount: Number(amount),
+  };
+  return createApiRequest<TxHashResponse>({
     baseUrl: SERVER_URL,
     endpoint: "/approve",
     method: "POST",
-    body: {
-      username: username + ".hydentity",
-      password,
-      token,
-      spender,
-      amount: Number(amount),
-    },
+    body,
   })();
 }
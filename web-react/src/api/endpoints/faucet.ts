@@ -1,4 +1,5 @@
 import { SERVER_URL } from "../constants";
+import { FaucetRequest, TxHashResponse } from "../types";
 import { createApiRequest } from "../createApiRequest";
 
 interface FaucetParams {
@@ -7,13 +8,14 @@ interface FaucetParams {
 }
 
 export default async function faucet({ username, token }: FaucetParams) {
-  return createApiRequest({
+  const body: FaucetRequest = {
+    username: username + ".hydentity",
+    token,
+  };
+  return createApiRequest<TxHashResponse>({
     baseUrl: SERVER_URL,
     endpoint: "/faucet",
     method: "POST",
-    body: {
-      username: username + ".hydentity",
-      token,
-    },
+    body,
   })();
 }
@@ -1,16 +1,18 @@
 import { createApiRequest } from "../createApiRequest";
 import { AuthParams, SERVER_URL } from "../constants";
+import { RegisterRequest, TxHashResponse } from "../types";
 
 interface RegisterParams extends AuthParams {}
 
 export default async function register({ username, password }: RegisterParams) {
-  return createApiRequest({
+  const body: RegisterRequest = {
+    username: username + ".hydentity",
+    password,
+  };
+  return createApiRequest<TxHashResponse>({
     baseUrl: SERVER_URL,
     endpoint: "/register",
     method: "POST",
-    body: {
-      username: username + ".hydentity",
-      password,
-    },
+    body,
   })();
 }
@@ -1,5 +1,6 @@
 import { createApiRequest } from "../createApiRequest";
 import { AuthParams, SERVER_URL } from "../constants";
+import { SwapRequest, TxHashResponse } from "../types";
 
 interface SwapParams extends AuthParams {
   fromToken: string;
@@ -14,16 +15,17 @@ export default async function swap({
   toToken,
   amount,
 }: SwapParams) {
-  return createApiRequest({
+  const body: SwapRequest = {
+    username: username + ".hydentity",
+    password,
+    token_a: fromToken,
+    token_b: toToken,
+    amount: Number(amount),
+  };
+  return createApiRequest<TxHashResponse>({
     baseUrl: SERVER_URL,
     endpoint: "/swap",
     method: "POST",
-    body: {
-      username: username + ".hydentity",
-      password,
-      token_a: fromToken,
-      token_b: toToken,
-      amount: Number(amount),
-    },
+    body,
   })();
 }
@@ -1,5 +1,6 @@
 import { createApiRequest } from "../createApiRequest";
 import { AuthParams, SERVER_URL } from "../constants";
+import { TransferRequest, TxHashResponse } from "../types";
 
 interface TransferParams extends AuthParams {
   recipient: string;
@@ -14,16 +15,17 @@ export default async function transfer({
   token,
   amount,
 }: TransferParams) {
-  return createApiRequest({
+  const body: TransferRequest = {
+    username: username + ".hydentity",
+    password,
+    recipient,
+    token,
+    amount: Number(amount),
+  };
+  return createApiRequest<TxHashResponse>({
     baseUrl: SERVER_URL,
     endpoint: "/transfer",
     method: "POST",
-    body: {
-      username: username + ".hydentity",
-      password,
-      recipient,
-      token,
-      amount: Number(amount),
-    },
+    body,
   })();
 }
@@ -0,0 +1,275 @@
+// Schemas of the OpenAPI document of the server (`cargo run --bin server -- openapi`), written
+// by hand. Replace this file with the output of `pnpm generate:api`.
+
+export interface components {
+  schemas: {
+    AllowanceDelta: {
+      owner: string;
+      spender: string;
+      before: number;
+      after: number;
+    };
+    /** @description Allows `spender` to move `amount` of `token` on behalf of `username`. */
+    ApproveRequest: {
+      username: string;
+      password: string;
+      spender: string;
+      token: string;
+      amount: number;
+    };
+    BalanceDelta: {
+      account: string;
+      before: number;
+      after: number;
+    };
+    /**
+     * @description Ordered operations packed by `/api/batch` into one atomic transaction with a single
+     *     identity proof.
+     */
+    BatchRequest: {
+      username: string;
+      password: string;
+      operations: components["schemas"]["Operation"][];
+    };
+    /**
+     * @description Prices of `token_a` in `token_b` over one interval. Intervals without any observation are
+     *     skipped.
+     */
+    Candle: {
+      /** @description Start of the interval, in seconds since the unix epoch. */
+      start: number;
+      open: number;
+      high: number;
+      low: number;
+      close: number;
+      /** @description Amounts swapped through this server during the interval. */
+      volume_a: number;
+      volume_b: number;
+    };
+    CandlesResponse: {
+      token_a: string;
+      token_b: string;
+      interval: number;
+      candles: components["schemas"]["Candle"][];
+    };
+    /** @description Outcome of a single readiness check. */
+    Check: {
+      ok: boolean;
+      detail: string;
+    };
+    /** @description Sends the configured faucet amount of `token` to `username`. */
+    FaucetRequest: {
+      /** @description Full identity, e.g. `bob.hydentity`. */
+      username: string;
+      token: string;
+    };
+    /** @description A request field that failed validation. */
+    FieldError: {
+      field: string;
+      message: string;
+    };
+    /** @description Detailed readiness report served on `/ready`, with a 503 status when `ready` is false. */
+    HealthReport: {
+      ready: boolean;
+      node: components["schemas"]["Check"];
+      indexer: components["schemas"]["Check"];
+      state_sync: components["schemas"]["Check"];
+      prover: components["schemas"]["Check"];
+      faucet: components["schemas"]["Check"];
+    };
+    /** @description An operation of a transaction involving an identity, as sender or counterparty. */
+    HistoryEntry: {
+      tx_hash: string;
+      operation: components["schemas"]["HistoryOperation"];
+      /** @description Identity of the transaction. */
+      identity: string;
+      /** @description Recipient, spender or owner of the operation. */
+      counterparty?: string | null;
+      tokens: string[];
+      /** @description Amounts of `tokens`, for swaps the amount sent then the quoted amount received. */
+      amounts: number[];
+      /** @description Last status reported by the indexer, `Sequenced` until it settles. */
+      status: string;
+      /** @description Seconds since the unix epoch, unknown for backfilled entries. */
+      submitted_at?: number | null;
+    };
+    /** @enum {string} */
+    HistoryOperation:
+      | "transfer"
+      | "transfer_from"
+      | "approve"
+      | "swap"
+      | "register"
+      | "external";
+    HistoryResponse: {
+      entries: components["schemas"]["HistoryEntry"][];
+      /** @description Entries matching the filters, across all pages. */
+      total: number;
+    };
+    /** @description Registration of a hydentity account, according to the local contract state. */
+    IdentityResponse: {
+      identity: string;
+      registered: boolean;
+      /** @description Number of times the identity was verified on-chain, absent when not registered. */
+      nonce?: number | null;
+      /** @description Whether the identity contract is able to change the credentials of its identities. */
+      rotation_supported: boolean;
+    };
+    /** @description A single operation of a composite transaction, sent from the request identity. */
+    Operation:
+      | {
+          recipient: string;
+          token: string;
+          amount: number;
+          /** @enum {string} */
+          type: "transfer";
+        }
+      | {
+          spender: string;
+          token: string;
+          amount: number;
+          /** @enum {string} */
+          type: "approve";
+        }
+      | {
+          owner: string;
+          recipient: string;
+          token: string;
+          amount: number;
+          /** @enum {string} */
+          type: "transfer_from";
+        }
+      | {
+          token_a: string;
+          token_b: string;
+          amount: number;
+          /** @enum {string} */
+          type: "swap";
+        }
+      | {
+          /** @enum {string} */
+          type: "register";
+        };
+    PoolDelta: {
+      token_a: string;
+      token_b: string;
+      reserves_before: number[];
+      reserves_after: number[];
+    };
+    /** @description Reserves, prices and activity of an AMM pair. */
+    PoolResponse: {
+      token_a: string;
+      token_b: string;
+      reserve_a: number;
+      reserve_b: number;
+      /** @description Amount of `token_b` one `token_a` is worth at the current reserves. */
+      price_a_in_b?: number | null;
+      price_b_in_a?: number | null;
+      /** @description Constant product `reserve_a * reserve_b`, absent when it overflows. */
+      k?: number | null;
+      reference_token: string;
+      /** @description Value of both reserves in `reference_token`, absent when a token has no pool with it. */
+      tvl?: number | null;
+      /** @description Amounts swapped through this server over the last 24 hours, in and out of the pool. */
+      volume_24h_a: number;
+      volume_24h_b: number;
+      swaps_24h: number;
+    };
+    /**
+     * @description Amount of `token_b` received when swapping `amount_a` of `token_a`, from the server's
+     *     view of the AMM reserves.
+     */
+    QuoteResponse: {
+      token_a: string;
+      token_b: string;
+      amount_a: number;
+      amount_b: number;
+    };
+    /** @description Registers a new hydentity account. */
+    RegisterRequest: {
+      username: string;
+      password: string;
+    };
+    /**
+     * @description Runs `operations` in a single transaction against a copy of the server states, without
+     *     sending anything to the node.
+     */
+    SimulateRequest: {
+      username: string;
+      password: string;
+      operations: components["schemas"]["Operation"][];
+    };
+    /** @description State changes a simulated transaction would make, per contract. */
+    SimulateResponse: {
+      tokens: components["schemas"]["TokenDelta"][];
+      pools: components["schemas"]["PoolDelta"][];
+      /** @description Identities the transaction registers. */
+      registered: string[];
+    };
+    /** @description Swaps `amount` of `token_a` against `token_b` through the AMM. */
+    SwapRequest: {
+      username: string;
+      password: string;
+      token_a: string;
+      token_b: string;
+      amount: number;
+    };
+    TokenDelta: {
+      token: string;
+      balances: components["schemas"]["BalanceDelta"][];
+      allowances: components["schemas"]["AllowanceDelta"][];
+    };
+    /**
+     * @description Moves `amount` of `token` from `owner` to `recipient`, using the allowance `owner` gave
+     *     to `username`.
+     */
+    TransferFromRequest: {
+      /** @description Full identity of the spender, e.g. `bob.hydentity`. */
+      username: string;
+      password: string;
+      /** @description Account the tokens are taken from. */
+      owner: string;
+      recipient: string;
+      token: string;
+      amount: number;
+    };
+    TransferRequest: {
+      /** @description Full identity of the sender, e.g. `bob.hydentity`. */
+      username: string;
+      password: string;
+      /** @description Identity or contract name receiving the tokens. */
+      recipient: string;
+      token: string;
+      amount: number;
+    };
+    /** @description Time-weighted average prices of a pair over observed blocks. */
+    TwapResponse: {
+      token_a: string;
+      token_b: string;
+      /** @description Seconds actually covered, shorter than asked while the history is short. */
+      window: number;
+      from_block: number;
+      to_block: number;
+      price_a_in_b: number;
+      price_b_in_a: number;
+    };
+    /** @description Blob transaction submitted by the server, its proof follows asynchronously. */
+    TxHashResponse: {
+      tx_hash: string;
+    };
+    TxStatusResponse: {
+      tx_hash: string;
+      /** @description Status reported by the indexer, e.g. `Success`, `Failure` or `TimedOut`. */
+      status: string;
+    };
+    /** @description Body of the 400 responses returned when a request fails validation. */
+    ValidationErrors: {
+      errors: components["schemas"]["FieldError"][];
+    };
+  };
+  responses: never;
+  parameters: never;
+  requestBodies: never;
+  headers: never;
+  pathItems: never;
+}
@@ -0,0 +1,11 @@
+// Payloads of the hyleoof server, generated in `schema.ts` from its OpenAPI document.
+import type { components } from "./schema";
+
+type Schemas = components["schemas"];
+
+export type FaucetRequest = Schemas["FaucetRequest"];
+export type TransferRequest = Schemas["TransferRequest"];
+export type ApproveRequest = Schemas["ApproveRequest"];
+export type SwapRequest = Schemas["SwapRequest"];
+export type RegisterRequest = Schemas["RegisterRequest"];
+export type TxHashResponse = Schemas["TxHashResponse"];